@@ -2,4 +2,5 @@ import os
 
 PROJECT_DIRECTORY = "/home/smarius/Documents/copy-service/"
 DAEMON_HOST = "127.0.0.1"
-DAEMON_PORT = 8080
\ No newline at end of file
+DAEMON_PORT = 8080
+DAEMON_SOCKET = f"{PROJECT_DIRECTORY}/daemon/copy-service.sock"
\ No newline at end of file
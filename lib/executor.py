@@ -1,18 +1,30 @@
-from models.requests import BaseOperation 
+from models.requests import BaseOperation
 from typing import List
 import socket
 import json
 
-class Executor: 
-    def __init__(self, host: str, port: int): 
-        self.host = host 
-        self.port = port 
-    
-    def exec(self, operation: BaseOperation) -> str: 
-        with socket.socket(socket.AF_INET, socket.SOCK_STREAM) as s:
-            s.connect((self.host, self.port))
-            s.sendall(operation.repr().encode())
-            return json.loads(s.recv(4096).decode()) 
-    
-    def exec_bulk(self, operations: List[BaseOperation]) -> List[str]: 
-        return [self.exec(operation) for operation in operations]
\ No newline at end of file
+# Requests and responses are JSON documents, one per line.
+# Connecting through the Unix domain socket at `path` identifies the caller, who becomes the owner of its jobs.
+class Executor:
+    def __init__(self, host: str = None, port: int = None, path: str = None):
+        self.host = host
+        self.port = port
+        self.path = path
+
+    def connect(self) -> socket.socket:
+        if self.path is not None:
+            s = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+            s.connect(self.path)
+            return s
+        return socket.create_connection((self.host, self.port))
+
+    def exec(self, operation: BaseOperation) -> str:
+        return self.exec_bulk([operation])[0]
+
+    # Sends every request over a single connection before reading the responses, which come back in the same order.
+    def exec_bulk(self, operations: List[BaseOperation]) -> List[str]:
+        with self.connect() as s:
+            s.sendall("".join(operation.repr() + "\n" for operation in operations).encode())
+
+            with s.makefile("r", encoding="utf-8") as responses:
+                return [json.loads(responses.readline()) for _ in operations]
@@ -1,6 +1,7 @@
 from abc import ABC, abstractmethod
 from dataclasses import dataclass
 from enum import Enum
+import json
 
 class OperationType(Enum): 
     CREATE = 0, 
@@ -9,6 +10,8 @@ class OperationType(Enum):
     PROGRESS = 3, 
     LIST = 4
     RESUME = 5,
+    PRIORITIZE = 6,
+    MOVE_TO_FRONT = 7,
 
 class BaseOperation(ABC): 
     @abstractmethod
@@ -21,15 +24,21 @@ class BaseOperation(ABC):
 
 @dataclass
 class CreateOperation(BaseOperation):
-    def __init__(self, source: str, destination: str): 
+    def __init__(self, source: str, destination: str, **options): 
         self.source = source
         self.destination = destination
+        self.options = options # e.g. verify="sha256", atomic=True, priority="high"
     
     def type(self) -> OperationType:
         return OperationType.CREATE
 
     def repr(self) -> str:
-        return f'{{"request_type": "copy", "source_path": "{self.source}", "destination_path": "{self.destination}"}}'
+        return json.dumps({
+            "request_type": "copy",
+            "source_path": self.source,
+            "destination_path": self.destination,
+            **self.options,
+        })
 
 
 @dataclass
@@ -54,6 +63,29 @@ class ResumeOperation(BaseOperation):
     def repr(self) -> str:
         return f'{{"request_type": "resume", "job_id": "{self.job_id}"}}'
 
+@dataclass
+class PrioritizeOperation(BaseOperation):
+    def __init__(self, job_id: str, priority: str): 
+        self.job_id = job_id
+        self.priority = priority # "low", "normal", "high" or "urgent"
+    
+    def type(self) -> OperationType:
+        return OperationType.PRIORITIZE
+
+    def repr(self) -> str:
+        return f'{{"request_type": "prioritize", "job_id": "{self.job_id}", "priority": "{self.priority}"}}'
+
+@dataclass
+class MoveToFrontOperation(BaseOperation):
+    def __init__(self, job_id: str): 
+        self.job_id = job_id
+    
+    def type(self) -> OperationType:
+        return OperationType.MOVE_TO_FRONT
+
+    def repr(self) -> str:
+        return f'{{"request_type": "move_to_front", "job_id": "{self.job_id}"}}'
+
 @dataclass
 class CancelOperation(BaseOperation):
     def __init__(self, job_id: str): 
@@ -9,6 +9,8 @@ class ResponseType(Enum):
     PROGRESS = 3
     LIST = 4
     RESUME = 5, 
+    PRIORITIZE = 6
+    MOVE_TO_FRONT = 7
 
 @dataclass
 class BaseResponse:
@@ -21,7 +23,7 @@ class CreateResponse(BaseResponse):
 
     @staticmethod
     def from_json(obj: dict) -> 'CreateResponse':
-        if obj.get("message", None) is not None: 
+        if obj.get("error", False):
             return CreateResponse(type=ResponseType.CREATE, error=obj["message"], job_id="")
         return CreateResponse(type=ResponseType.CREATE, error=None, job_id=obj.get("job_id", None))
 
@@ -31,7 +33,7 @@ class SuspendResponse(BaseResponse):
 
     @staticmethod
     def from_json(obj: dict) -> 'SuspendResponse':
-        if "error" in obj.get("message", "").lower(): 
+        if obj.get("error", False):
             return SuspendResponse(type=ResponseType.SUSPEND, error=obj["message"], message="")
         return SuspendResponse(type=ResponseType.SUSPEND, message=obj["message"], error="")
 
@@ -41,17 +43,37 @@ class ResumeResponse(BaseResponse):
 
     @staticmethod
     def from_json(obj: dict) -> 'SuspendResponse':
-        if "error" in obj.get("message", "").lower(): 
+        if obj.get("error", False):
             return ResumeResponse(type=ResponseType.RESUME, error=obj["message"], message="")
         return ResumeResponse(type=ResponseType.RESUME, message=obj["message"], error="")
 
+@dataclass
+class PrioritizeResponse(BaseResponse):
+    message: str
+
+    @staticmethod
+    def from_json(obj: dict) -> 'PrioritizeResponse':
+        if obj.get("error", False):
+            return PrioritizeResponse(type=ResponseType.PRIORITIZE, error=obj["message"], message="")
+        return PrioritizeResponse(type=ResponseType.PRIORITIZE, message=obj["message"], error="")
+
+@dataclass
+class MoveToFrontResponse(BaseResponse):
+    message: str
+
+    @staticmethod
+    def from_json(obj: dict) -> 'MoveToFrontResponse':
+        if obj.get("error", False):
+            return MoveToFrontResponse(type=ResponseType.MOVE_TO_FRONT, error=obj["message"], message="")
+        return MoveToFrontResponse(type=ResponseType.MOVE_TO_FRONT, message=obj["message"], error="")
+
 @dataclass
 class CancelResponse(BaseResponse):
     message: str
 
     @staticmethod
     def from_json(obj: dict) -> 'CancelResponse':
-        if "error" in obj.get("message", "").lower(): 
+        if obj.get("error", False):
             return CancelResponse(type=ResponseType.CANCEL, error=obj["message"], message="")
         return CancelResponse(type=ResponseType.CANCEL, message=obj["message"], error="")
 
@@ -60,9 +82,23 @@ class JobResponse(BaseResponse):
     id: str
     source: str
     destination: str 
+    priority: str
     status: str
     writes: str
+    files: int
+    bytes: int
+    stored: int
+    total: int
     percentage: float
+    digest: str
+    resolution: object
+    attempts: int
+    created_at: int
+    started_at: int
+    finished_at: int
+    throughput: float
+    average_throughput: float
+    eta: float
     
     @staticmethod
     def from_json(obj: dict, type: ResponseType) -> 'JobResponse':
@@ -72,9 +108,23 @@ class JobResponse(BaseResponse):
             id=obj.get('id', None),
             source=obj.get('source', None),
             destination=obj.get('destination', None),
+            priority=obj.get('priority', None),
             status=obj.get('status', None),
             writes=obj.get('writes', 0), 
+            files=obj.get('files', 0),
+            bytes=obj.get('bytes', 0),
+            stored=obj.get('stored', 0),
+            total=obj.get('total', None),
             percentage=obj.get('percentage', 0),
+            digest=obj.get('digest', None),
+            resolution=obj.get('resolution', None),
+            attempts=obj.get('attempts', 1),
+            created_at=obj.get('created_at', None),
+            started_at=obj.get('started_at', None),
+            finished_at=obj.get('finished_at', None),
+            throughput=obj.get('throughput', None),
+            average_throughput=obj.get('average_throughput', None),
+            eta=obj.get('eta', None),
         )
     
 @dataclass
@@ -88,5 +138,5 @@ class ListResponse(BaseResponse):
     jobs: List[JobResponse]
 
     @staticmethod
-    def from_json(obj_list: List) -> 'ListResponse':
-        return [JobResponse.from_json(obj, ResponseType.LIST) for obj in obj_list]
\ No newline at end of file
+    def from_json(obj: dict) -> 'ListResponse':
+        return [JobResponse.from_json(job, ResponseType.LIST) for job in obj.get("jobs", [])]
\ No newline at end of file
@@ -38,6 +38,20 @@ class Test():
             time.sleep(0.5)
             os.system("clear")
     
+    def create_directory(self):
+        create_response = CreateResponse.from_json(self.executor.exec(
+            CreateOperation(
+                source=f"{PROJECT_DIRECTORY}/src",
+                destination=f"{PROJECT_DIRECTORY}/daemon/tmp/src")))
+        print(create_response)
+
+        for _ in range(120):
+            response = ProgressResponse.from_json(self.executor.exec(ProgressOperation(create_response.job_id)))
+            percentage = 0 if response.percentage is None else response.percentage * 100
+            print(f"{response.id} -> {response.files} files, {response.writes} ({percentage:.2f}%)")
+            time.sleep(0.5)
+            os.system("clear")
+
     def create_source_path_does_not_exist(self):        
         requests = [
             CreateOperation(
@@ -142,6 +156,7 @@ class Test():
 test = Test(Executor(DAEMON_HOST, DAEMON_PORT))
 
 # test.create_multiple_files()
+# test.create_directory()
 # test.create_source_path_does_not_exist()
 # test.suspend()
 # test.cancel()
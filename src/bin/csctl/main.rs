@@ -0,0 +1,506 @@
+use std::{path, process, thread, time::Duration};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Parser, Subcommand};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use copy_service::client::responses::{EventResponse, JobResponse};
+use copy_service::models::job::{ConflictPolicy, CopyStrategy, HashAlgorithm, JobOptions, Preserve, Priority, RetryPolicy, Timestamps};
+use copy_service::models::query::{JobQuery, SortField, SortOrder};
+use copy_service::{ClientError, CopyServiceClient};
+
+mod output;
+
+use output::Bars;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Command-line client of the copy-service daemon.
+///
+/// Exit codes: 0 on success, 1 if the daemon refused the request, 2 on invalid usage, 3 if the daemon could
+/// not be reached or answered unexpectedly, 4 if a waited for job did not complete.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Connect through the daemon's Unix domain socket, jobs submitted through it are owned by the caller
+    #[arg(short, long, value_name = "PATH", env = "CSCTL_SOCKET", global = true)]
+    socket: Option<String>,
+
+    /// Host of the daemon's TCP listener, used unless a socket is given
+    #[arg(long, default_value = "127.0.0.1", env = "CSCTL_HOST", global = true)]
+    host: String,
+
+    /// Port of the daemon's TCP listener
+    #[arg(long, default_value_t = 8080, env = "CSCTL_PORT", global = true)]
+    port: u16,
+
+    /// Print the daemon's responses as JSON, one document per line, instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Submit a copy job and print its id
+    Copy {
+        source: String,
+        destination: String,
+
+        #[arg(long, default_value = "normal", value_parser = parser::<Priority>(&["low", "normal", "high", "urgent"]))]
+        priority: Priority,
+        /// Verify the copy with a digest of the source
+        #[arg(long, value_parser = parser::<HashAlgorithm>(&["crc32c", "xxh3", "sha256"]))]
+        verify: Option<HashAlgorithm>,
+        /// Write to a temporary file, renamed over the destination once complete
+        #[arg(long)]
+        atomic: bool,
+        /// What to do when the destination exists
+        #[arg(long, default_value = "overwrite", value_parser = parser::<ConflictPolicy>(&["overwrite", "skip", "fail", "rename", "update"]))]
+        conflict: ConflictPolicy,
+        /// Metadata to preserve, comma separated
+        #[arg(long, value_delimiter = ',', value_parser = parser::<Preserve>(&["mode", "timestamps", "ownership", "xattrs", "acls"]))]
+        preserve: Vec<Preserve>,
+        #[arg(long, default_value = "auto", value_parser = parser::<CopyStrategy>(&["auto", "reflink", "copy_file_range", "sendfile", "buffered"]))]
+        strategy: CopyStrategy,
+        /// Attempts to make, the first one included, before the job fails. Replaces the daemon's retry policy, with the default backoff
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_attempts: Option<u32>,
+
+        /// Label the job, can be given more than once
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Wait for the job to finish
+        #[arg(long)]
+        wait: bool,
+        /// Wait for the job to finish, showing a live progress bar
+        #[arg(long)]
+        watch: bool,
+    },
+    /// List the jobs, all of them unless filtered
+    List {
+        /// Only jobs in one of these statuses, comma separated (e.g. running,failed)
+        #[arg(long, value_delimiter = ',')]
+        status: Vec<String>,
+        /// Only jobs copying from under this path
+        #[arg(long, value_name = "PATH")]
+        source: Option<String>,
+        /// Only jobs copying to under this path
+        #[arg(long, value_name = "PATH")]
+        destination: Option<String>,
+        /// Only jobs owned by this uid
+        #[arg(long, value_name = "UID")]
+        owner: Option<u32>,
+        /// Only jobs with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only jobs submitted within the last this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        newer_than: Option<u64>,
+        /// Only jobs submitted more than this many seconds ago
+        #[arg(long, value_name = "SECONDS")]
+        older_than: Option<u64>,
+        #[arg(long, default_value = "created", value_parser = parser::<SortField>(&["created", "started", "finished", "priority"]))]
+        sort: SortField,
+        /// Sort in descending order
+        #[arg(long)]
+        desc: bool,
+        /// Jobs per page
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Where to continue, as printed with the previous page
+        #[arg(long)]
+        cursor: Option<String>,
+
+        /// Show live progress bars until every listed job is finished
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Show the progress of one or more jobs
+    Progress {
+        #[arg(required = true)]
+        job_ids: Vec<String>,
+
+        /// Show live progress bars until every job is finished
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Suspend a queued or running job
+    Suspend { job_id: String },
+    /// Resume a suspended job
+    Resume { job_id: String },
+    /// Cancel a job
+    Cancel { job_id: String },
+    /// Change the priority of a job
+    Prioritize {
+        job_id: String,
+        #[arg(value_parser = parser::<Priority>(&["low", "normal", "high", "urgent"]))]
+        priority: Priority,
+    },
+    /// Move a queued job to the front of the queue
+    MoveToFront { job_id: String },
+    /// Queue a failed, canceled or unverified job again
+    Retry { job_id: String },
+    /// Submit a new job with the source, destination, priority and options of an existing one
+    Clone {
+        job_id: String,
+
+        /// Copy this instead of the job's source
+        #[arg(long)]
+        source: Option<String>,
+        /// Copy to this instead of the job's destination
+        #[arg(long)]
+        destination: Option<String>,
+        #[arg(long, value_parser = parser::<Priority>(&["low", "normal", "high", "urgent"]))]
+        priority: Option<Priority>,
+
+        /// Verify the copy with a digest of the source
+        #[arg(long, value_parser = parser::<HashAlgorithm>(&["crc32c", "xxh3", "sha256"]), conflicts_with = "no_verify")]
+        verify: Option<HashAlgorithm>,
+        /// Do not verify the copy
+        #[arg(long)]
+        no_verify: bool,
+        /// Write to a temporary file, renamed over the destination once complete
+        #[arg(long, conflicts_with = "no_atomic")]
+        atomic: bool,
+        /// Write to the destination directly
+        #[arg(long)]
+        no_atomic: bool,
+        /// What to do when the destination exists
+        #[arg(long, value_parser = parser::<ConflictPolicy>(&["overwrite", "skip", "fail", "rename", "update"]))]
+        conflict: Option<ConflictPolicy>,
+        /// Metadata to preserve, comma separated, replaces the job's list
+        #[arg(long, value_delimiter = ',', value_parser = parser::<Preserve>(&["mode", "timestamps", "ownership", "xattrs", "acls"]))]
+        preserve: Vec<Preserve>,
+        #[arg(long, value_parser = parser::<CopyStrategy>(&["auto", "reflink", "copy_file_range", "sendfile", "buffered"]))]
+        strategy: Option<CopyStrategy>,
+        /// Attempts to make, the first one included, before the job fails. Replaces the job's retry policy, with the default backoff
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_attempts: Option<u32>,
+        /// Label the job, can be given more than once, replaces the job's tags
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Wait for the job to finish
+        #[arg(long)]
+        wait: bool,
+        /// Wait for the job to finish, showing a live progress bar
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Forget a finished job
+    Remove { job_id: String },
+    /// Forget every finished job
+    Purge {
+        /// Only jobs that finished at least this many seconds ago
+        #[arg(long, value_name = "SECONDS")]
+        older_than: Option<u64>,
+    },
+    /// Print events about a job until it is finished, or about every job until interrupted
+    Subscribe {
+        job_id: Option<String>,
+
+        /// Seconds between progress events, 0 for none
+        #[arg(long, default_value_t = 1.0)]
+        interval: f64,
+    },
+}
+
+// Accepts the names the protocol uses for the values of `T`.
+fn parser<T: DeserializeOwned + Clone + Send + Sync + 'static>(names: &'static [&'static str]) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(names).map(|name| serde_json::from_value(json!(name)).unwrap())
+}
+
+// Why a command did not succeed, each with its own exit code.
+enum Failure {
+    Client(ClientError),
+    Unsuccessful, // a job that was waited for did not complete
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Failure::Client(ClientError::Rejected(_)) => 1,
+            Failure::Client(_) => 3,
+            Failure::Unsuccessful => 4,
+        }
+    }
+}
+
+impl From<ClientError> for Failure {
+    fn from(err: ClientError) -> Self {
+        Failure::Client(err)
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let client = match &args.socket {
+        Some(path) => CopyServiceClient::connect_unix(path),
+        None => CopyServiceClient::connect_tcp((args.host.trim_start_matches('[').trim_end_matches(']'), args.port)),
+    };
+
+    let result = match client {
+        Ok(mut client) => run(&args, &mut client),
+        Err(err) => {
+            match &args.socket {
+                Some(path) => eprintln!("Error, could not connect to the daemon at {}: {}", path, err),
+                None => eprintln!("Error, could not connect to the daemon at {}:{}: {}", args.host, args.port, err),
+            }
+            process::exit(3);
+        }
+    };
+
+    match result {
+        Ok(()) => {}
+        Err(Failure::Client(ClientError::Rejected(message))) if args.json => {
+            println!("{}", json!({ "error": true, "message": message }));
+            process::exit(1);
+        }
+        Err(failure) => {
+            if let Failure::Client(err) = &failure {
+                eprintln!("Error, {}", err);
+            }
+            process::exit(failure.exit_code());
+        }
+    }
+}
+
+fn run(args: &Args, client: &mut CopyServiceClient) -> Result<(), Failure> {
+    match &args.command {
+        Command::Copy { source, destination, priority, verify, atomic, conflict, preserve, strategy, max_attempts, tags, wait, watch } => {
+            let options = JobOptions {
+                verify: *verify,
+                atomic: *atomic,
+                conflict: *conflict,
+                preserve: preserve.clone(),
+                strategy: *strategy,
+                retry: max_attempts.map(|max_attempts| RetryPolicy { max_attempts, ..RetryPolicy::default() }),
+                tags: tags.clone(),
+            };
+            let job_id = client.copy(&absolute(source), &absolute(destination), *priority, options)?;
+            submitted(args, client, job_id, *wait, *watch)
+        }
+        Command::Clone { job_id, source, destination, priority, wait, watch, .. } => {
+            let source = source.as_deref().map(absolute);
+            let destination = destination.as_deref().map(absolute);
+            let job_id = client.clone_job(job_id, source.as_deref(), destination.as_deref(), *priority, option_overrides(&args.command))?;
+            submitted(args, client, job_id, *wait, *watch)
+        }
+        Command::List { watch: false, .. } => {
+            let page = client.list_matching(&query(&args.command))?;
+            match args.json {
+                true => print_json(&page),
+                false => {
+                    print!("{}", output::table(&page.jobs));
+                    if let Some(cursor) = &page.next_cursor {
+                        println!("{} of {} jobs, continue with --cursor {}", page.jobs.len(), page.total, cursor);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::List { watch: true, .. } => {
+            let query = query(&args.command);
+            let mut bars = Bars::new();
+            loop {
+                let jobs = client.list_matching(&query)?.jobs;
+                let done = jobs.iter().all(JobResponse::is_finished);
+                if args.json {
+                    print_json(&jobs);
+                } else if done {
+                    bars.finish(&jobs);
+                } else {
+                    bars.draw(&jobs);
+                }
+
+                if done {
+                    return Ok(());
+                }
+                thread::sleep(REFRESH_INTERVAL);
+            }
+        }
+        Command::Progress { job_ids, watch: false } => {
+            let jobs = progress(client, job_ids)?;
+            match args.json {
+                true => jobs.iter().for_each(print_json),
+                false => print!("{}", output::table(&jobs)),
+            }
+            Ok(())
+        }
+        Command::Progress { job_ids, watch: true } => {
+            let jobs = follow(args, client, job_ids)?;
+            finished(args, &jobs)
+        }
+        Command::Suspend { job_id } => message(args, client.suspend(job_id)?),
+        Command::Resume { job_id } => message(args, client.resume(job_id)?),
+        Command::Cancel { job_id } => message(args, client.cancel(job_id)?),
+        Command::Prioritize { job_id, priority } => message(args, client.prioritize(job_id, *priority)?),
+        Command::MoveToFront { job_id } => message(args, client.move_to_front(job_id)?),
+        Command::Retry { job_id } => message(args, client.retry(job_id)?),
+        Command::Remove { job_id } => message(args, client.remove(job_id)?),
+        Command::Purge { older_than } => {
+            let purged = client.purge(older_than.map(Duration::from_secs))?;
+            match args.json {
+                true => print_json(&purged),
+                false => println!("{}", purged.message),
+            }
+            Ok(())
+        }
+        Command::Subscribe { job_id, interval } => {
+            let interval = Duration::try_from_secs_f64(*interval).ok().filter(|interval| !interval.is_zero());
+            let mut last = None;
+            for event in client.subscribe(job_id.as_deref(), interval)? {
+                let event = event?;
+                match args.json {
+                    true => print_json(&event),
+                    false => println!("{}", output::event(&event)),
+                }
+                if let EventResponse::Finished(job) = event {
+                    last = Some(job);
+                }
+            }
+
+            match (job_id, last) {
+                (Some(_), Some(job)) => finished(args, &[job]),
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+// Prints the id of a new job and, if asked to, waits for it.
+fn submitted(args: &Args, client: &mut CopyServiceClient, job_id: String, wait: bool, watch: bool) -> Result<(), Failure> {
+    match args.json {
+        true => println!("{}", json!({ "job_id": job_id })),
+        false => println!("{}", job_id),
+    }
+
+    if watch {
+        let jobs = follow(args, client, &[job_id])?;
+        return finished(args, &jobs);
+    }
+    if wait {
+        let job = client.wait_for_completion(&job_id, None)?;
+        if args.json {
+            print_json(&job);
+        }
+        return finished(args, &[job]);
+    }
+    Ok(())
+}
+
+// The filters, sorting and page of a list command.
+fn query(command: &Command) -> JobQuery {
+    let Command::List { status, source, destination, owner, tag, newer_than, older_than, sort, desc, limit, cursor, .. } = command else {
+        return JobQuery::default();
+    };
+
+    let now = Timestamps::now();
+    JobQuery {
+        status: status.clone(),
+        source_prefix: source.as_deref().map(absolute),
+        destination_prefix: destination.as_deref().map(absolute),
+        owner: *owner,
+        tag: tag.clone(),
+        created_after: newer_than.map(|seconds| now.saturating_sub(seconds.saturating_mul(1000))),
+        created_before: older_than.map(|seconds| now.saturating_sub(seconds.saturating_mul(1000))),
+        sort: *sort,
+        order: if *desc { SortOrder::Desc } else { SortOrder::Asc },
+        limit: *limit,
+        cursor: cursor.clone(),
+    }
+}
+
+// The options a clone command changes, the clone keeps the original job's value of every other option.
+fn option_overrides(command: &Command) -> Map<String, Value> {
+    let Command::Clone { verify, no_verify, atomic, no_atomic, conflict, preserve, strategy, max_attempts, tags, .. } = command else {
+        return Map::new();
+    };
+
+    let mut options = Map::new();
+    if verify.is_some() || *no_verify {
+        options.insert("verify".to_string(), json!(verify));
+    }
+    if *atomic || *no_atomic {
+        options.insert("atomic".to_string(), json!(atomic));
+    }
+    if let Some(conflict) = conflict {
+        options.insert("conflict".to_string(), json!(conflict));
+    }
+    if !preserve.is_empty() {
+        options.insert("preserve".to_string(), json!(preserve));
+    }
+    if let Some(strategy) = strategy {
+        options.insert("strategy".to_string(), json!(strategy));
+    }
+    if let Some(max_attempts) = *max_attempts {
+        options.insert("retry".to_string(), json!(RetryPolicy { max_attempts, ..RetryPolicy::default() }));
+    }
+    if !tags.is_empty() {
+        options.insert("tags".to_string(), json!(tags));
+    }
+    options
+}
+
+// Responses that carry just a message.
+fn message(args: &Args, message: String) -> Result<(), Failure> {
+    match args.json {
+        true => println!("{}", json!({ "message": message })),
+        false => println!("{}", message),
+    }
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string(value).unwrap());
+}
+
+fn progress(client: &mut CopyServiceClient, job_ids: &[String]) -> Result<Vec<JobResponse>, ClientError> {
+    job_ids.iter().map(|job_id| client.progress(job_id)).collect()
+}
+
+// Shows the progress of the jobs until they are all finished and returns their last progress.
+fn follow(args: &Args, client: &mut CopyServiceClient, job_ids: &[String]) -> Result<Vec<JobResponse>, ClientError> {
+    let mut bars = Bars::new();
+    loop {
+        let jobs = progress(client, job_ids)?;
+        let done = jobs.iter().all(JobResponse::is_finished);
+        if args.json {
+            jobs.iter().for_each(print_json);
+        } else if done {
+            bars.finish(&jobs);
+        } else {
+            bars.draw(&jobs);
+        }
+
+        if done {
+            return Ok(jobs);
+        }
+        thread::sleep(REFRESH_INTERVAL);
+    }
+}
+
+// Reports jobs that were waited for, failing unless every one of them completed.
+fn finished(args: &Args, jobs: &[JobResponse]) -> Result<(), Failure> {
+    let unsuccessful: Vec<_> = jobs.iter().filter(|job| !job.is_successful()).collect();
+    if !args.json {
+        for job in &unsuccessful {
+            eprintln!("Job {} did not complete: {}", job.id, job.status);
+        }
+    }
+
+    match unsuccessful.is_empty() {
+        true => Ok(()),
+        false => Err(Failure::Unsuccessful),
+    }
+}
+
+// The daemon resolves relative paths against its own working directory, not the caller's.
+fn absolute(source: &str) -> String {
+    path::absolute(source).map(|path| path.to_string_lossy().into_owned()).unwrap_or_else(|_| source.to_string())
+}
@@ -0,0 +1,132 @@
+use std::io::{self, IsTerminal, Write};
+use copy_service::client::responses::{EventResponse, JobResponse};
+
+const BAR_WIDTH: usize = 30;
+
+pub fn table(jobs: &[JobResponse]) -> String {
+    let mut rows = vec![["ID", "STATUS", "PRIORITY", "PROGRESS", "COPIED", "RATE", "ETA", "SOURCE", "DESTINATION"].map(String::from)];
+    rows.extend(jobs.iter().map(|job| {
+        [
+            job.id.clone(),
+            job.status.clone(),
+            format!("{:?}", job.priority).to_lowercase(),
+            format!("{:.1}%", job.percentage * 100.0),
+            human(job.bytes),
+            rate(job),
+            job.eta.map_or_else(|| "-".to_string(), duration),
+            job.source.clone(),
+            job.destination.clone(),
+        ]
+    }));
+
+    let mut widths = [0; 9];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<_> = row.iter().zip(widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+pub fn event(event: &EventResponse) -> String {
+    match event {
+        EventResponse::Status { job_id, status } => format!("{}  status    {}", job_id, status),
+        EventResponse::Progress(job) =>
+            format!("{}  progress  {:.1}% {}", job.id, job.percentage.clamp(0.0, 1.0) * 100.0, human(job.bytes)),
+        EventResponse::Finished(job) => format!("{}  finished  {}", job.id, job.status),
+    }
+}
+
+// Progress bars redrawn in place. When stdout is not a terminal only the last frame is printed.
+pub struct Bars {
+    drawn: usize, // nr. of lines of the previous frame, to be overwritten
+    terminal: bool,
+}
+
+impl Bars {
+    pub fn new() -> Self {
+        Bars { drawn: 0, terminal: io::stdout().is_terminal() }
+    }
+
+    pub fn draw(&mut self, jobs: &[JobResponse]) {
+        if self.terminal {
+            self.render(jobs);
+        }
+    }
+
+    pub fn finish(&mut self, jobs: &[JobResponse]) {
+        self.render(jobs);
+    }
+
+    fn render(&mut self, jobs: &[JobResponse]) {
+        let mut frame = String::new();
+        if self.drawn > 0 {
+            frame.push_str(&format!("\x1b[{}A", self.drawn));
+        }
+        for job in jobs {
+            let fraction = job.percentage.clamp(0.0, 1.0);
+            let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+            frame.push_str(&format!(
+                "\x1b[2K{} [{}{}] {:>5.1}% {:>10} {:>12} {:>8}  {}\n",
+                job.id.chars().take(8).collect::<String>(),
+                "#".repeat(filled),
+                ".".repeat(BAR_WIDTH - filled),
+                fraction * 100.0,
+                human(job.bytes),
+                rate(job),
+                job.eta.map_or_else(String::new, duration),
+                job.status,
+            ));
+        }
+        // a job that disappeared leaves lines of the previous frame behind
+        for _ in jobs.len()..self.drawn {
+            frame.push_str("\x1b[2K\n");
+        }
+        if !self.terminal {
+            frame = frame.replace("\x1b[2K", "");
+        }
+
+        self.drawn = jobs.len().max(self.drawn);
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(frame.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+
+// The current rate while the job is copying, the average one once it stopped.
+fn rate(job: &JobResponse) -> String {
+    match job.throughput.or(job.average_throughput) {
+        Some(rate) => format!("{}/s", human(rate as u64)),
+        None => "-".to_string(),
+    }
+}
+
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.ceil() as u64;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+pub fn human(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
@@ -0,0 +1,22 @@
+use clap::Parser;
+
+// Command-line options of the daemon.
+#[derive(Debug, Parser)]
+#[command(version, about = "Daemon that copies files and directories on behalf of its clients")]
+pub struct Args {
+    /// Config file to read
+    #[arg(short, long, value_name = "PATH", default_value = "./Config.toml")]
+    pub config: String,
+
+    /// Stay attached to the terminal and log to it instead of daemonizing, no pid file is written
+    #[arg(short, long)]
+    pub foreground: bool,
+
+    /// Override a field of the config file, e.g. `--set max_threads=8` (repeatable)
+    #[arg(short = 's', long = "set", value_name = "FIELD=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// Check the config and exit, with status 0 if it is valid and 1 otherwise
+    #[arg(long)]
+    pub check_config: bool,
+}
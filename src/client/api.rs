@@ -0,0 +1,254 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::client::requests::*;
+use crate::client::responses::*;
+use crate::models::job::{JobOptions, Priority};
+use crate::models::query::JobQuery;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error), // connecting to the daemon, sending a request or receiving a response
+    Closed, // the daemon closed the connection before responding
+    Protocol(String), // the response is not what the request calls for
+    Rejected(String), // the daemon answered with an error
+    Timeout, // the job did not finish in time
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{}", err),
+            ClientError::Closed => write!(f, "The daemon closed the connection"),
+            ClientError::Protocol(message) => write!(f, "Invalid response from the daemon, {}", message),
+            ClientError::Rejected(message) => write!(f, "{}", message),
+            ClientError::Timeout => write!(f, "Timed out waiting for the job to finish"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+// A connection to the daemon. Requests are sent one at a time and answered in order.
+pub struct CopyServiceClient {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    poll_interval: Duration, // how often `wait_for_completion` asks for progress
+}
+
+impl CopyServiceClient {
+    pub fn connect_tcp(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        Ok(CopyServiceClient::new(Box::new(stream.try_clone()?), Box::new(stream)))
+    }
+
+    // Jobs submitted through the daemon's Unix domain socket are owned by the calling user.
+    pub fn connect_unix(path: impl AsRef<Path>) -> Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        Ok(CopyServiceClient::new(Box::new(stream.try_clone()?), Box::new(stream)))
+    }
+
+    fn new(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Self {
+        CopyServiceClient { reader: BufReader::new(reader), writer, poll_interval: Duration::from_millis(500) }
+    }
+
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    // Submits a copy job and returns its id. Relative paths are resolved by the daemon, against its working directory.
+    pub fn copy(&mut self, source: &str, destination: &str, priority: Priority, options: JobOptions) -> Result<String> {
+        let request = CopyJobRequest {
+            base: JobRequest { request_type: JobRequestType::Copy },
+            source_path: source.to_string(),
+            destination_path: destination.to_string(),
+            priority,
+            options,
+        };
+        self.request::<_, CopyResponse>(&request).map(|response| response.job_id)
+    }
+
+    pub fn suspend(&mut self, job_id: &str) -> Result<String> {
+        let request = SuspendJobRequest { base: JobRequest { request_type: JobRequestType::Suspend }, job_id: job_id.to_string() };
+        self.request::<_, SuspendResponse>(&request).map(|response| response.message)
+    }
+
+    pub fn resume(&mut self, job_id: &str) -> Result<String> {
+        let request = ResumeJobRequest { base: JobRequest { request_type: JobRequestType::Resume }, job_id: job_id.to_string() };
+        self.request::<_, ResumeResponse>(&request).map(|response| response.message)
+    }
+
+    pub fn cancel(&mut self, job_id: &str) -> Result<String> {
+        let request = CancelJobRequest { base: JobRequest { request_type: JobRequestType::Cancel }, job_id: job_id.to_string() };
+        self.request::<_, CancelResponse>(&request).map(|response| response.message)
+    }
+
+    pub fn prioritize(&mut self, job_id: &str, priority: Priority) -> Result<String> {
+        let request = PrioritizeJobRequest {
+            base: JobRequest { request_type: JobRequestType::Prioritize },
+            job_id: job_id.to_string(),
+            priority,
+        };
+        self.request::<_, PrioritizeResponse>(&request).map(|response| response.message)
+    }
+
+    pub fn move_to_front(&mut self, job_id: &str) -> Result<String> {
+        let request = MoveToFrontJobRequest { base: JobRequest { request_type: JobRequestType::MoveToFront }, job_id: job_id.to_string() };
+        self.request::<_, MoveToFrontResponse>(&request).map(|response| response.message)
+    }
+
+    // Queues a failed, canceled or unverified job again, under the same id.
+    pub fn retry(&mut self, job_id: &str) -> Result<String> {
+        let request = RetryJobRequest { base: JobRequest { request_type: JobRequestType::Retry }, job_id: job_id.to_string() };
+        self.request::<_, RetryResponse>(&request).map(|response| response.message)
+    }
+
+    // Submits a copy of the job, with whatever is given instead of its source, destination and priority. `options`
+    // holds the option fields to change, named and valued as in `JobOptions` (`{"verify": "sha256"}`), the others
+    // are kept. Returns the id of the new job.
+    pub fn clone_job(
+        &mut self,
+        job_id: &str,
+        source: Option<&str>,
+        destination: Option<&str>,
+        priority: Option<Priority>,
+        options: Map<String, Value>,
+    ) -> Result<String> {
+        let request = CloneJobRequest {
+            base: JobRequest { request_type: JobRequestType::Clone },
+            job_id: job_id.to_string(),
+            source_path: source.map(String::from),
+            destination_path: destination.map(String::from),
+            priority,
+            options,
+        };
+        self.request::<_, CopyResponse>(&request).map(|response| response.job_id)
+    }
+
+    // Forgets a finished job.
+    pub fn remove(&mut self, job_id: &str) -> Result<String> {
+        let request = RemoveJobRequest { base: JobRequest { request_type: JobRequestType::Remove }, job_id: job_id.to_string() };
+        self.request::<_, RemoveResponse>(&request).map(|response| response.message)
+    }
+
+    // Forgets every finished job the caller can access, or only those that finished at least `older_than` ago.
+    pub fn purge(&mut self, older_than: Option<Duration>) -> Result<PurgeResponse> {
+        let request = PurgeJobsRequest {
+            base: JobRequest { request_type: JobRequestType::Purge },
+            older_than: older_than.map(|older_than| older_than.as_secs()),
+        };
+        self.request(&request)
+    }
+
+    pub fn progress(&mut self, job_id: &str) -> Result<JobResponse> {
+        let request = ProgressJobRequest { base: JobRequest { request_type: JobRequestType::Progress }, job_id: job_id.to_string() };
+        self.request(&request)
+    }
+
+    // Every job the caller can access, in the order they were submitted.
+    pub fn list(&mut self) -> Result<Vec<JobResponse>> {
+        self.list_matching(&JobQuery::default()).map(|page| page.jobs)
+    }
+
+    // One page of the jobs matching the query, pass its `next_cursor` as the query's cursor for the next one.
+    pub fn list_matching(&mut self, query: &JobQuery) -> Result<ListResponse> {
+        self.request(&ListJobsRequest { base: JobRequest { request_type: JobRequestType::List }, query: query.clone() })
+    }
+
+    // Follows one job, or every job the caller can access, with a progress event every `interval` (None for no
+    // progress events). The connection is taken over until the subscription ends, when the job is finished.
+    pub fn subscribe(&mut self, job_id: Option<&str>, interval: Option<Duration>) -> Result<Subscription<'_>> {
+        let request = SubscribeRequest {
+            base: JobRequest { request_type: JobRequestType::Subscribe },
+            job_id: job_id.map(String::from),
+            interval: interval.map_or(0.0, |interval| interval.as_secs_f64()),
+        };
+        self.request::<_, SubscribeResponse>(&request)?;
+        Ok(Subscription { client: self, single: job_id.is_some(), ended: false })
+    }
+
+    // Polls the job until it is finished and returns its last progress, whether it completed or not.
+    pub fn wait_for_completion(&mut self, job_id: &str, timeout: Option<Duration>) -> Result<JobResponse> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let job = self.progress(job_id)?;
+            if job.is_finished() {
+                return Ok(job);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() + self.poll_interval > deadline) {
+                return Err(ClientError::Timeout);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    // Sends a request and reads its response, error responses become `ClientError::Rejected`.
+    pub fn request<Req: Serialize, Resp: DeserializeOwned>(&mut self, request: &Req) -> Result<Resp> {
+        let mut line = serde_json::to_string(request).map_err(|err| ClientError::Protocol(err.to_string()))?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.receive()
+    }
+
+    fn receive<Resp: DeserializeOwned>(&mut self) -> Result<Resp> {
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(ClientError::Closed);
+        }
+
+        let response: Value = serde_json::from_str(&response).map_err(|err| ClientError::Protocol(err.to_string()))?;
+        if response.get("error").and_then(Value::as_bool).unwrap_or(false) {
+            let error: ErrorMessageResponse = serde_json::from_value(response).map_err(|err| ClientError::Protocol(err.to_string()))?;
+            return Err(ClientError::Rejected(error.message));
+        }
+        serde_json::from_value(response).map_err(|err| ClientError::Protocol(err.to_string()))
+    }
+}
+
+// Events of a subscription, in the order they happened. Ends after the job is finished when following a single job.
+pub struct Subscription<'a> {
+    client: &'a mut CopyServiceClient,
+    single: bool,
+    ended: bool,
+}
+
+impl Iterator for Subscription<'_> {
+    type Item = Result<EventResponse>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+
+        let event = self.client.receive::<EventResponse>();
+        self.ended = match &event {
+            Ok(EventResponse::Finished(_)) => self.single,
+            Ok(_) => false,
+            Err(_) => true,
+        };
+        Some(event)
+    }
+}
@@ -1,70 +1,193 @@
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
 
 use crate::client::handlers::*;
+use crate::client::listener::Listener;
 use crate::client::requests::*;
+use crate::client::stream::Stream;
 use crate::models::config::Config;
-use crate::models::job::Job;
+use crate::models::job::Owner;
+use crate::services::access;
+use crate::services::copy::Command;
+use crate::services::scheduler::Scheduler;
 use crate::services::storage::StorageService;
 
+// Requests longer than this are rejected and the connection is closed.
+const MAX_REQUEST_SIZE: u64 = 1 << 20;
+
+#[derive(Clone)]
 pub struct Client {
+    config: Arc<Config>,
     storage: Arc<RwLock<StorageService>>,
-    sender: Sender<Job>,
-    config: Arc<RwLock<Config>>,
+    scheduler: Arc<RwLock<Scheduler>>,
+    sender: Sender<Command>,
+    connections: Arc<AtomicUsize>, // nr. of connections being served
+}
+
+// Holds one of the `max_connections` slots for as long as a connection is served.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn acquire(connections: &Arc<AtomicUsize>, max_connections: usize) -> Option<Self> {
+        connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < max_connections).then_some(count + 1))
+            .ok()
+            .map(|_| ConnectionSlot(connections.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl Client {
-    pub fn new(storage: Arc<RwLock<StorageService>>, sender: Sender<Job>, config: Arc<RwLock<Config>>) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        storage: Arc<RwLock<StorageService>>,
+        scheduler: Arc<RwLock<Scheduler>>,
+        sender: Sender<Command>,
+    ) -> Self {
         Client {
+            config,
             storage,
+            scheduler,
             sender,
-            config,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Accepts connections on every listener, each on a thread of its own.
+    pub fn listen(&mut self, listeners: Vec<Listener>) {        
+        let handles: Vec<_> = listeners
+            .into_iter()
+            .map(|listener| {
+                let client = self.clone();
+                thread::spawn(move || loop {
+                    match listener.accept() {
+                        Ok(stream) => client.accept(stream),
+                        Err(e) => eprintln!("Error accepting connection: {}", e),
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let _ = handle.join();
         }
     }
 
-    pub fn listen(&mut self) {        
-        let listener = TcpListener::bind("127.0.0.1:8080").expect("Failed to bind to address");
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => self.handle_stream(stream),
-                Err(e) => eprintln!("Error accepting connection: {}", e),
+    // Serves every connection on its own thread, connections over the limit are turned away with an error.
+    fn accept(&self, mut stream: Stream) {
+        let slot = match ConnectionSlot::acquire(&self.connections, self.config.max_connections) {
+            Some(slot) => slot,
+            None => {
+                let error = handle_error(anyhow!("Too many connections, at most {} are served at a time", self.config.max_connections)).ok();
+                return self.send_response(&mut stream, &error);
             }
+        };
+
+        let client = self.clone();
+        let spawned = thread::Builder::new().spawn(move || {
+            let _slot = slot;
+            client.handle_stream(stream);
+        });
+
+        if let Err(err) = spawned {
+            eprintln!("Error spawning connection thread: {}", err);
         }
     }
 
-    fn handle_stream(&self, mut stream: TcpStream) {
+    // Requests and responses are JSON documents, one per line. Requests are handled in the order they arrive,
+    // a client may send several of them before reading any response.
+    fn handle_stream(&self, stream: Stream) {
+        let idle_timeout = Some(Duration::from_secs(self.config.idle_timeout)).filter(|timeout| !timeout.is_zero());
+        let setup = stream
+            .set_timeout(idle_timeout)
+            .and_then(|_| Ok((stream.peer()?, stream.try_clone()?)));
+
+        let (caller, mut writer) = match setup {
+            Ok(setup) => setup,
+            Err(err) => return eprintln!("Error setting up connection: {}", err),
+        };
+        let mut reader = BufReader::new(stream);
         let mut buffer: Vec<u8> = Vec::new();
-        buffer.resize(65536, 0);
 
-        while let Ok(bytes_read) = stream.read(&mut buffer) {
-            if bytes_read == 0 {
-                break;
+        loop {
+            buffer.clear();
+            match (&mut reader).take(MAX_REQUEST_SIZE).read_until(b'\n', &mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) if bytes_read as u64 == MAX_REQUEST_SIZE && !buffer.ends_with(b"\n") => {
+                    let error = handle_error(anyhow!("Request exceeds {} bytes", MAX_REQUEST_SIZE)).ok();
+                    self.send_response(&mut writer, &error);
+                    break;
+                }
+                Ok(_) => {}
+                // idle for longer than `idle_timeout`
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(err) => {
+                    eprintln!("Error reading request: {}", err);
+                    break;
+                }
+            }
+
+            let request = String::from_utf8_lossy(&buffer);
+            if request.trim().is_empty() {
+                continue;
+            }
+            match parse_request(&request) {
+                // the subscription takes over the connection until it ends
+                Ok(AnyRequest::Subscribe(subscribe_request)) => {
+                    if handle_subscribe(subscribe_request, caller, self.storage.clone(), &mut writer).is_err() {
+                        break;
+                    }
+                }
+                parsed_request => self.send_response(&mut writer, &self.handle_request(parsed_request, caller)),
             }
-            
-            let request = String::from_utf8_lossy(&buffer[..bytes_read]);
-            self.send_response(&mut stream,&self.handle_request(&request));
-            buffer.resize(65536, 0)
         }
     }
 
-    fn handle_request(&self, request: &str) -> Option<String> {
-        let response = match parse_request(request) {
+    // `caller` is who sent the request, if the listener could tell. It becomes the owner of the jobs it submits.
+    fn handle_request(&self, request: anyhow::Result<AnyRequest>, caller: Option<Owner>) -> Option<String> {
+        let response = match request {
             Ok(parsed_request) => {
                 match parsed_request {
+                    // anybody able to reach a tcp listener would otherwise copy whatever they like as root
+                    AnyRequest::Copy(_) | AnyRequest::Clone(_) if caller.is_none() && access::is_root() && !self.config.allow_anonymous_jobs =>
+                        handle_error(anyhow!("Jobs can only be submitted through the Unix socket, the daemon runs as root")),
                     AnyRequest::Copy(copy_request) => 
-                        handle_copy(copy_request, self.sender.clone()),
+                        handle_copy(copy_request, caller, self.storage.clone(), self.sender.clone()),
                     AnyRequest::Suspend(suspend_request) =>
-                        handle_suspend(suspend_request, self.storage.clone()),
+                        handle_suspend(suspend_request, caller, self.storage.clone()),
                     AnyRequest::Cancel(cancel_request) =>
-                        handle_cancel(cancel_request, self.storage.clone()),
+                        handle_cancel(cancel_request, caller, self.storage.clone(), self.sender.clone()),
                     AnyRequest::Progress(progress_request) => 
-                        handle_progress(progress_request, self.storage.clone(), self.config.clone()),
-                    AnyRequest::List(_) => 
-                        handle_list(self.storage.clone(), self.config.clone()),
+                        handle_progress(progress_request, caller, self.storage.clone()),
+                    AnyRequest::List(list_request) => 
+                        handle_list(list_request, caller, self.storage.clone()),
                     AnyRequest::Resume(resume_request) => 
-                        handle_resume(resume_request, self.storage.clone()),
+                        handle_resume(resume_request, caller, self.storage.clone(), self.sender.clone()),
+                    AnyRequest::Prioritize(prioritize_request) => 
+                        handle_prioritize(prioritize_request, caller, self.storage.clone()),
+                    AnyRequest::MoveToFront(move_to_front_request) => 
+                        handle_move_to_front(move_to_front_request, caller, self.storage.clone(), self.scheduler.clone()),
+                    AnyRequest::Retry(retry_request) =>
+                        handle_retry(retry_request, caller, self.storage.clone(), self.sender.clone()),
+                    AnyRequest::Clone(clone_request) =>
+                        handle_clone(clone_request, caller, self.storage.clone(), self.sender.clone()),
+                    AnyRequest::Remove(remove_request) =>
+                        handle_remove(remove_request, caller, self.storage.clone()),
+                    AnyRequest::Purge(purge_request) =>
+                        handle_purge(purge_request, caller, self.storage.clone()),
+                    AnyRequest::Subscribe(_) => 
+                        handle_error(anyhow!("Subscriptions are served by the connection")),
                 }
             }, 
             Err(err) => handle_error(err),
@@ -76,9 +199,9 @@ impl Client {
         }
     }
 
-    fn send_response(&self, stream: &mut TcpStream, response: &Option<String>) {
+    fn send_response(&self, stream: &mut Stream, response: &Option<String>) {
         if let Some(response_str) = response {
-            if let Err(err) = stream.write_all(response_str.as_bytes()) {
+            if let Err(err) = stream.write_all(format!("{}\n", response_str).as_bytes()) {
                 eprintln!("Error sending response: {}", err);
             }
         }
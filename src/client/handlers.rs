@@ -1,86 +1,352 @@
+use std::io::Write;
 use std::sync::Arc;
 use std::sync::RwLock;
-use std::sync::mpsc::Sender;
-use anyhow::Result;
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
 
 use crate::client::requests::*;
 use crate::client::responses::*;
-use crate::models::config::Config;
-use crate::models::job::Job;
+use crate::client::stream::Stream;
+use crate::models::job::{Job, JobOptions, JobStatus, Owner, RetryPolicy, Timestamps};
+use crate::services::copy::Command;
+use crate::services::scheduler::Scheduler;
 use crate::services::storage::StorageService;
 
-pub fn handle_copy(request: CopyJobRequest, sender: Sender<Job>)
+// How long a subscriber may have hung up before its connection is closed.
+const HANGUP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Jobs of other users are reported as if they did not exist, and jobs without an owner as if they could not be changed.
+fn is_controllable(storage_service: &Arc<RwLock<StorageService>>, job_id: &str, caller: Option<Owner>) -> bool {
+    storage_service.read().unwrap().job(job_id.to_string()).is_some_and(|job| job.is_controllable_by(caller))
+}
+
+pub fn handle_copy(request: CopyJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>)
     -> Result<String> {
-    let job = Job::new(request.source_path,request.destination_path);
-    match sender.send(job.clone()) {
+    let job = Job { owner: caller, ..Job::new(request.source_path, request.destination_path, request.options) };
+    *job.priority.write().unwrap() = request.priority;
+
+    submit(job, storage_service, sender)
+}
+
+// The job is recorded before responding, so that requests pipelined after this one already see it.
+fn submit(job: Job, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>) -> Result<String> {
+    if let Some(Err(message)) = job.options.retry.as_ref().map(RetryPolicy::validate) {
+        return Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Invalid retry policy, {}", message)))?);
+    }
+
+    let job = storage_service.write().unwrap().add_job(job);
+    match sender.send(Command::Dispatch(job.clone())) {
         Ok(_) => 
             Ok(serde_json::to_string(&CopyResponse{ job_id: job.id.to_string() })?),
         Err(err) => 
-            Ok(serde_json::to_string(&ErrorMessageResponse{ message: format!("Could not copy, {}", err) })?),
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not copy, {}", err)))?),
     } 
 }
 
-pub fn handle_suspend(request: SuspendJobRequest, storage_service: Arc<RwLock<StorageService>>) 
+pub fn handle_suspend(request: SuspendJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) 
     -> Result<String> {
     
-    match storage_service.write().unwrap().suspend_job(request.job_id.clone()) {
+    match is_controllable(&storage_service, &request.job_id, caller) && storage_service.write().unwrap().suspend_job(request.job_id.clone()) {
         true => 
             Ok(serde_json::to_string(&SuspendResponse { message: format!("Job {} suspended successfully", request.job_id) })?),
         false => 
-            Ok(serde_json::to_string(&ErrorMessageResponse{ message: format!("Could not suspend job {}", request.job_id) })?),
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not suspend job {}", request.job_id)))?),
     }
 }
 
-pub fn handle_resume(request: ResumeJobRequest, storage_service: Arc<RwLock<StorageService>>) 
+pub fn handle_resume(request: ResumeJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>) 
     -> Result<String> {
     
-    match storage_service.write().unwrap().resume_job(request.job_id.clone()) {
+    let (resumed, parked_job) = match is_controllable(&storage_service, &request.job_id, caller) {
+        true => storage_service.write().unwrap().resume_job(request.job_id.clone()),
+        false => (false, None),
+    };
+    if let Some(job) = parked_job {
+        sender.send(Command::Dispatch(job))?;
+    }
+
+    match resumed {
         true => 
             Ok(serde_json::to_string(&ResumeResponse { message: format!("Job {} resumed successfully", request.job_id) })?),
         false => 
-            Ok(serde_json::to_string(&ResumeResponse { message: format!("Could not resume job {}", request.job_id) })?),
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not resume job {}", request.job_id)))?),
     }
 }
 
-pub fn handle_cancel(request: CancelJobRequest, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
-    match storage_service.write().unwrap().cancel_job(request.job_id.clone()) {
+pub fn handle_cancel(request: CancelJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>) -> Result<String> {
+    let (canceled, parked_job) = match is_controllable(&storage_service, &request.job_id, caller) {
+        true => storage_service.write().unwrap().cancel_job(request.job_id.clone()),
+        false => (false, None),
+    };
+    if let Some(job) = parked_job {
+        sender.send(Command::Dispatch(job))?;
+    }
+
+    match canceled {
         true => 
             Ok(serde_json::to_string(&CancelResponse { message: format!("Job {} cancelled successfully", request.job_id) })?),
         false => 
-            Ok(serde_json::to_string(&ErrorMessageResponse { message: format!("Could not cancel job {}", request.job_id) })?),
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not cancel job {}", request.job_id)))?),
+    }
+}
+
+pub fn handle_retry(request: RetryJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>) -> Result<String> {
+    let (requeued, parked_job) = match is_controllable(&storage_service, &request.job_id, caller) {
+        true => storage_service.write().unwrap().requeue_job(request.job_id.clone()),
+        false => (false, None),
+    };
+    if let Some(job) = parked_job {
+        sender.send(Command::Dispatch(job))?;
+    }
+
+    match requeued {
+        true => 
+            Ok(serde_json::to_string(&RetryResponse { message: format!("Job {} queued again", request.job_id) })?),
+        false => 
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not retry job {}, only failed, canceled or unverified jobs can be retried", request.job_id)))?),
+    }
+}
+
+// The clone belongs to the caller and starts from scratch, answered like a copy request.
+pub fn handle_clone(request: CloneJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, sender: Sender<Command>) -> Result<String> {
+    let original = match storage_service.read().unwrap().job(request.job_id.clone()).filter(|job| job.is_accessible_by(caller)) {
+        Some(job) => job,
+        None => return Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not find job {}", request.job_id)))?),
+    };
+
+    // the fields given replace the original's, an unknown field is ignored like it is in a copy request
+    let mut options = serde_json::to_value(&original.options)?;
+    if let Value::Object(fields) = &mut options {
+        fields.extend(request.options);
+    }
+    let options: JobOptions = match serde_json::from_value(options) {
+        Ok(options) => options,
+        Err(err) => return Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Invalid options, {}", err)))?),
+    };
+
+    let job = Job {
+        owner: caller,
+        ..Job::new(
+            request.source_path.unwrap_or_else(|| original.source.clone()),
+            request.destination_path.unwrap_or_else(|| original.destination.clone()),
+            options,
+        )
+    };
+    *job.priority.write().unwrap() = request.priority.unwrap_or(*original.priority.read().unwrap());
+
+    submit(job, storage_service, sender)
+}
+
+pub fn handle_remove(request: RemoveJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
+    let job = storage_service.read().unwrap().job(request.job_id.clone()).filter(|job| job.is_controllable_by(caller));
+    let removed = match job {
+        Some(job) => match storage_service.write().unwrap().remove_jobs(|candidate| candidate.id == job.id) {
+            Ok(removed) => !removed.is_empty(),
+            Err(err) => return handle_error(err),
+        },
+        None => false,
+    };
+
+    match removed {
+        true => 
+            Ok(serde_json::to_string(&RemoveResponse { message: format!("Job {} removed", request.job_id) })?),
+        false => 
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not remove job {}, only finished jobs can be removed", request.job_id)))?),
     }
 }
 
-pub fn handle_progress(request: ProgressJobRequest, storage_service: Arc<RwLock<StorageService>>, config: Arc<RwLock<Config>>) -> Result<String> {
-    match storage_service.read().unwrap().job(request.job_id.clone()) {
+pub fn handle_purge(request: PurgeJobsRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
+    let cutoff = request.older_than.map(|seconds| Timestamps::now().saturating_sub(seconds.saturating_mul(1000)));
+    let removed = storage_service.write().unwrap().remove_jobs(|job| {
+        let finished = job.timestamps.read().unwrap().finished;
+        job.is_controllable_by(caller) && cutoff.is_none_or(|cutoff| finished.is_some_and(|finished| finished <= cutoff))
+    });
+    let removed: Vec<_> = match removed {
+        Ok(removed) => removed.iter().map(|id| id.to_string()).collect(),
+        Err(err) => return handle_error(err),
+    };
+
+    Ok(serde_json::to_string(&PurgeResponse { message: format!("Removed {} finished job(s)", removed.len()), removed })?)
+}
+
+// Queued jobs are picked by priority, changing the priority of a suspended job applies once it is resumed.
+pub fn handle_prioritize(request: PrioritizeJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
+    let storage = storage_service.read().unwrap();
+    match storage.job(request.job_id.clone()).filter(|job| job.is_controllable_by(caller)) {
+        Some(job) if !job.status.read().unwrap().is_finished() => {
+            storage.update_job_priority(job, request.priority);
+            Ok(serde_json::to_string(&PrioritizeResponse { message: format!("Job {} prioritized successfully", request.job_id) })?)
+        }
+        _ => 
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not prioritize job {}", request.job_id)))?),
+    }
+}
+
+// The job is also raised to the highest priority among the other queued jobs, so that it is picked next.
+pub fn handle_move_to_front(
+    request: MoveToFrontJobRequest,
+    caller: Option<Owner>,
+    storage_service: Arc<RwLock<StorageService>>,
+    scheduler: Arc<RwLock<Scheduler>>,
+) -> Result<String> {
+    let storage = storage_service.read().unwrap();
+    let job = storage.job(request.job_id.clone()).filter(|job| job.is_controllable_by(caller));
+    let highest = job.as_ref().and_then(|job| scheduler.write().unwrap().move_to_front(job.id));
+
+    match (job, highest) {
+        (Some(job), Some(highest)) => {
+            if *job.priority.read().unwrap() < highest {
+                storage.update_job_priority(job, highest);
+            }
+            Ok(serde_json::to_string(&MoveToFrontResponse { message: format!("Job {} moved to the front of the queue", request.job_id) })?)
+        }
+        _ => 
+            Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not move job {} to the front, it is not queued", request.job_id)))?),
+    }
+}
+
+pub fn handle_progress(request: ProgressJobRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
+    match storage_service.read().unwrap().job(request.job_id.clone()).filter(|job| job.is_accessible_by(caller)) {
         Some(stats) => 
-            Ok(serde_json::to_string(&JobResponse::from_job(&stats, config))?),
+            Ok(serde_json::to_string(&JobResponse::from_job(&stats))?),
         None => 
-           Ok(serde_json::to_string(&ErrorMessageResponse { message: format!("Could not find job {}", request.job_id) })?),
+           Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not find job {}", request.job_id)))?),
     }
 }
 
-pub fn handle_list(storage_service: Arc<RwLock<StorageService>>, config: Arc<RwLock<Config>>) -> Result<String> {
-    let active_jobs: Vec<_> = 
-        storage_service
-            .read()
-            .unwrap()
-            .jobs()
-            .read()
-            .unwrap()
-            .clone()
-            .into_iter()
-            .collect();
+pub fn handle_list(request: ListJobsRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>) -> Result<String> {
+    if let Err(message) = request.query.validate() {
+        return Ok(serde_json::to_string(&ErrorMessageResponse::new(format!("Could not list jobs, {}", message)))?);
+    }
 
-    let response: Vec<_> = 
-        active_jobs
-            .iter()
-            .map(|job| JobResponse::from_job(job, config.clone()))
-            .collect();
+    let page = storage_service.read().unwrap().query(&request.query, caller);
+    let response = ListResponse {
+        jobs: page.jobs.iter().map(JobResponse::from_job).collect(),
+        total: page.total,
+        next_cursor: page.next_cursor,
+    };
 
     Ok(serde_json::to_string(&response)?)
 }
 
+// Streams events until the subscribed job is finished, forever when subscribed to every job. Returns an error once
+// the client hung up or cannot be written to, the connection is then closed.
+pub fn handle_subscribe(request: SubscribeRequest, caller: Option<Owner>, storage_service: Arc<RwLock<StorageService>>, stream: &mut Stream)
+    -> Result<()> {
+    if !request.interval.is_finite() || request.interval < 0.0 || (request.interval > 0.0 && request.interval < 0.1) {
+        return send(stream, &ErrorMessageResponse::new("Could not subscribe, interval has to be 0 or at least 0.1 seconds".to_string()));
+    }
+
+    // subscribed before looking at the job, so that no change goes unnoticed
+    let changes = storage_service.read().unwrap().subscribe();
+    let job = match &request.job_id {
+        Some(job_id) => match storage_service.read().unwrap().job(job_id.clone()).filter(|job| job.is_accessible_by(caller)) {
+            Some(job) => Some(job),
+            None => return send(stream, &ErrorMessageResponse::new(format!("Could not find job {}", job_id))),
+        },
+        None => None,
+    };
+    let is_watched = |candidate: &Arc<Job>| match &job {
+        Some(job) => job.id == candidate.id,
+        None => candidate.is_accessible_by(caller),
+    };
+
+    let message = match &job {
+        Some(job) => format!("Subscribed to job {}", job.id),
+        None => "Subscribed to all jobs".to_string(),
+    };
+    send(stream, &SubscribeResponse { message })?;
+
+    if let Some(job) = job.as_ref().filter(|job| job.status.read().unwrap().is_finished()) {
+        return send(stream, &EventResponse::Finished(JobResponse::from_job(job)));
+    }
+
+    let interval = Some(Duration::from_secs_f64(request.interval)).filter(|interval| !interval.is_zero());
+    let mut next_progress = interval.map(|interval| Instant::now() + interval);
+    loop {
+        // subscribers never send anything, so waking up regularly is the only way to notice one hung up
+        let timeout = match next_progress {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()).min(HANGUP_CHECK_INTERVAL),
+            None => HANGUP_CHECK_INTERVAL,
+        };
+        let change = changes.recv_timeout(timeout);
+        if stream.is_closed()? {
+            return Err(anyhow!("Subscriber hung up"));
+        }
+
+        match change {
+            Ok(change) if is_watched(&change.job) => {
+                send(stream, &EventResponse::Status { job_id: change.job.id.to_string(), status: JobResponse::status(&change.status) })?;
+                if change.status.is_finished() {
+                    send(stream, &EventResponse::Finished(JobResponse::from_job(&change.job)))?;
+                    if job.is_some() {
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) if next_progress.is_none_or(|deadline| Instant::now() < deadline) => {}
+            Err(RecvTimeoutError::Timeout) => {
+                let jobs = storage_service.read().unwrap().jobs();
+                let in_progress: Vec<_> = jobs
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|candidate| is_watched(candidate))
+                    .filter(|candidate| matches!(*candidate.status.read().unwrap(), JobStatus::Running | JobStatus::Resumed | JobStatus::Verifying))
+                    .cloned()
+                    .collect();
+                for candidate in &in_progress {
+                    send(stream, &EventResponse::Progress(JobResponse::from_job(candidate)))?;
+                }
+                next_progress = interval.map(|interval| Instant::now() + interval);
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn send<T: Serialize>(stream: &mut Stream, response: &T) -> Result<()> {
+    stream.write_all(format!("{}\n", serde_json::to_string(response)?).as_bytes())?;
+    Ok(())
+}
+
 pub fn handle_error(error: anyhow::Error) -> Result<String> {
-    Ok(serde_json::to_string(&ErrorMessageResponse { message:error.to_string() })?)
-}
\ No newline at end of file
+    Ok(serde_json::to_string(&ErrorMessageResponse::new(error.to_string()))?)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc;
+    use std::{fs, thread};
+    use uuid::Uuid;
+
+    #[test]
+    fn subscriptions_end_once_the_subscriber_hung_up() {
+        let path = std::env::temp_dir().join(format!("copy-service-handlers-{}.jsonl", Uuid::new_v4()));
+        let storage = Arc::new(RwLock::new(StorageService::new(&path).unwrap()));
+        let (client, server) = UnixStream::pair().unwrap();
+        let request: SubscribeRequest = serde_json::from_str(r#"{"request_type": "subscribe", "interval": 0}"#).unwrap();
+
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = Stream::Unix(server);
+            let _ = done.send(handle_subscribe(request, None, storage, &mut stream));
+        });
+
+        let mut reader = BufReader::new(client);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.contains("Subscribed to all jobs"));
+        drop(reader);
+
+        let result = finished.recv_timeout(HANGUP_CHECK_INTERVAL * 5);
+        fs::remove_file(&path).unwrap();
+        assert!(result.expect("subscription outlived its subscriber").is_err());
+    }
+}
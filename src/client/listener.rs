@@ -0,0 +1,67 @@
+use std::fs::{self, Permissions};
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+
+use crate::client::stream::Stream;
+use crate::models::config::{Config, Endpoint};
+
+// A bound endpoint, ready to accept connections.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    // Binds every listener of the config, failing on the first endpoint that cannot be bound.
+    pub fn bind_all(config: &Config) -> Result<Vec<Listener>> {
+        if config.listeners.is_empty() {
+            return Err(anyhow!("No listeners configured"));
+        }
+
+        config
+            .listeners
+            .iter()
+            .map(|endpoint| {
+                Listener::bind(endpoint, Path::new(&config.working_directory))
+                    .with_context(|| format!("Could not listen on {}", endpoint))
+            })
+            .collect()
+    }
+
+    fn bind(endpoint: &Endpoint, working_directory: &Path) -> Result<Listener> {
+        match endpoint {
+            Endpoint::Tcp { host, port } => {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                Ok(Listener::Tcp(TcpListener::bind((host, *port))?))
+            }
+            Endpoint::Unix { path, mode } => {
+                let path = working_directory.join(path);
+
+                // a socket left behind by a daemon that did not shut down cleanly is replaced, a live one is not
+                if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    if UnixStream::connect(&path).is_ok() {
+                        return Err(anyhow!("Another daemon is listening on {}", path.display()));
+                    }
+                    fs::remove_file(&path)?;
+                }
+
+                let listener = UnixListener::bind(&path)?;
+                if let Some(mode) = mode {
+                    fs::set_permissions(&path, Permissions::from_mode(*mode))?;
+                }
+                Ok(Listener::Unix(listener))
+            }
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+        }
+    }
+}
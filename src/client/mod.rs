@@ -1,4 +1,8 @@
 pub mod responses;
 pub mod handlers;
 pub mod requests;
+pub mod listener;
+pub mod stream;
+pub mod api;
+#[allow(clippy::module_inception)]
 pub mod client;
\ No newline at end of file
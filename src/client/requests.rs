@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
 use anyhow::{anyhow, Result};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::fmt::Debug;
 
+use crate::models::job::{JobOptions, Priority};
+use crate::models::query::JobQuery;
+
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "snake_case")]
 pub enum JobRequestType {
     Copy,
     Cancel, 
@@ -12,11 +15,18 @@ pub enum JobRequestType {
     Progress,
     List, 
     Resume,
+    Prioritize,
+    MoveToFront,
+    Subscribe,
+    Retry,
+    Clone,
+    Remove,
+    Purge,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JobRequest {
-    request_type: JobRequestType,
+    pub request_type: JobRequestType,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +36,12 @@ pub struct CopyJobRequest {
 
     pub source_path: String,
     pub destination_path: String,
+
+    #[serde(default)]
+    pub priority: Priority,
+
+    #[serde(flatten)]
+    pub options: JobOptions,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,6 +80,89 @@ pub struct ProgressJobRequest {
 pub struct ListJobsRequest {
     #[serde(flatten)]
     pub base: JobRequest,
+
+    #[serde(flatten)]
+    pub query: JobQuery,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrioritizeJobRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    pub job_id: String,
+    pub priority: Priority,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveToFrontJobRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    pub job_id: String,
+}
+
+// Turns the connection into a stream of events about one job, or about every job the caller can access.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default = "SubscribeRequest::default_interval")]
+    pub interval: f64, // seconds between progress events, 0 for none
+}
+
+impl SubscribeRequest {
+    fn default_interval() -> f64 {
+        1.0
+    }
+}
+
+// Queues a failed or canceled job again, it continues from its last checkpoint. One that failed verification starts over.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetryJobRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    pub job_id: String,
+}
+
+// Submits a new job copying what the job did, with whatever is given here instead.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloneJobRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    pub job_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub options: Map<String, Value>, // fields of the job's options to change, the others are kept
+}
+
+// Forgets a finished job.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveJobRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    pub job_id: String,
+}
+
+// Forgets every finished job the caller can access, or only those that finished more than `older_than` seconds ago.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeJobsRequest {
+    #[serde(flatten)]
+    pub base: JobRequest,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -74,14 +173,22 @@ pub enum AnyRequest {
     Progress(ProgressJobRequest),
     List(ListJobsRequest),
     Resume(ResumeJobRequest),
+    Prioritize(PrioritizeJobRequest),
+    MoveToFront(MoveToFrontJobRequest),
+    Subscribe(SubscribeRequest),
+    Retry(RetryJobRequest),
+    Clone(CloneJobRequest),
+    Remove(RemoveJobRequest),
+    Purge(PurgeJobsRequest),
 }
 
 pub fn parse_request(json_str: &str) -> Result<AnyRequest> {
     let value: Value = serde_json::from_str(json_str)?;
 
-    let req_type: JobRequestType = match serde_json::from_value(value.get("request_type").unwrap().clone()) {
-        Ok(result) => result,
-        Err(_) => return Err(anyhow!("Failed to deserialize request_type")),
+    let req_type: JobRequestType = match value.get("request_type").cloned().map(serde_json::from_value) {
+        Some(Ok(result)) => result,
+        Some(Err(_)) => return Err(anyhow!("Failed to deserialize request_type")),
+        None => return Err(anyhow!("Missing request_type")),
     };
 
     let result = match req_type {
@@ -109,6 +216,34 @@ pub fn parse_request(json_str: &str) -> Result<AnyRequest> {
             let resume_request: ResumeJobRequest = serde_json::from_str(json_str)?;
             AnyRequest::Resume(resume_request)
         }
+        JobRequestType::Prioritize => {
+            let prioritize_request: PrioritizeJobRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Prioritize(prioritize_request)
+        }
+        JobRequestType::MoveToFront => {
+            let move_to_front_request: MoveToFrontJobRequest = serde_json::from_str(json_str)?;
+            AnyRequest::MoveToFront(move_to_front_request)
+        }
+        JobRequestType::Subscribe => {
+            let subscribe_request: SubscribeRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Subscribe(subscribe_request)
+        }
+        JobRequestType::Retry => {
+            let retry_request: RetryJobRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Retry(retry_request)
+        }
+        JobRequestType::Clone => {
+            let clone_request: CloneJobRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Clone(clone_request)
+        }
+        JobRequestType::Remove => {
+            let remove_request: RemoveJobRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Remove(remove_request)
+        }
+        JobRequestType::Purge => {
+            let purge_request: PurgeJobsRequest = serde_json::from_str(json_str)?;
+            AnyRequest::Purge(purge_request)
+        }
     };
 
     Ok(result)
@@ -1,13 +1,20 @@
-use std::{fmt::Debug, sync::{Arc, RwLock}, fs};
+use std::{fmt::Debug, sync::Arc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::{job::{Job, JobStatus}, config::Config};
+use crate::models::job::{CopyStrategy, Job, JobStatus, Owner, Priority, Resolution};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorMessageResponse {
+    pub error: bool, // always true, tells errors apart from responses that only carry a message
     pub message: String,
 }
 
+impl ErrorMessageResponse {
+    pub fn new(message: String) -> Self {
+        ErrorMessageResponse { error: true, message }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CopyResponse {
     pub job_id: String,
@@ -28,59 +35,147 @@ pub struct CancelResponse {
     pub message: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrioritizeResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveToFrontResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListResponse {
+    pub jobs: Vec<JobResponse>,
+    pub total: usize, // jobs matching the query, on every page
+    pub next_cursor: Option<String>, // continues the listing after this page, None on the last one
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetryResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeResponse {
+    pub message: String,
+    pub removed: Vec<String>, // ids of the removed jobs
+}
+
+// Sent after a subscribe request is acknowledged, until the subscription ends.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventResponse {
+    Status { job_id: String, status: String }, // the job entered a new status
+    Progress(JobResponse), // sent every `interval` seconds for every job being copied or verified
+    Finished(JobResponse), // the job completed, failed or was canceled, follows its last status event
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JobResponse {
     pub id: String,
     pub source: String,
     pub destination: String,
+    pub owner: Option<Owner>,
 
+    pub priority: Priority,
     pub status: String,
     pub writes: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub stored: u64,
+    pub total: Option<u64>, // size of the source, known once the job started
     pub percentage: f64, 
+    pub digest: Option<String>,
+    pub resolution: Option<Resolution>,
+    pub method: Option<CopyStrategy>,
+    pub attempts: u32, // the current attempt, counting from 1
+
+    pub created_at: Option<u64>, // milliseconds since the Unix epoch
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub throughput: Option<f64>, // bytes per second over the last few seconds, while the job is copying
+    pub average_throughput: Option<f64>, // bytes per second over the time the job spent copying
+    pub eta: Option<f64>, // estimated seconds until the data is copied, while the job is copying
 }
 
 impl JobResponse {
-    pub fn from_job(job: &Arc<Job>, config: Arc<RwLock<Config>>) -> Self {
-        let job_clone = job.clone();
-        let writes = *job.writes.read().unwrap();
-
-        let status = {
-            let status_guard = job_clone.status.read().unwrap();
-            match &*status_guard {
-                JobStatus::Failed(message) => format!("failed: {}", message),
-                other => format!("{:?}", other),
-            }
+    pub fn from_job(job: &Arc<Job>) -> Self {
+        let status = job.status.read().unwrap().clone();
+        let bytes = *job.bytes.read().unwrap();
+        let total = *job.total.read().unwrap();
+        let timestamps = *job.timestamps.read().unwrap();
+        let (throughput, average_throughput) = {
+            let meter = job.throughput.lock().unwrap();
+            (meter.current(bytes), meter.average(bytes))
         };
-        
+
+        let eta = match (status.is_copying(), total, throughput.or(average_throughput)) {
+            (true, Some(total), Some(rate)) if rate > 0.0 => Some(total.saturating_sub(bytes) as f64 / rate),
+            _ => None,
+        };
+
         JobResponse { 
             id: job.id.to_string(),
             source: job.source.clone(), 
             destination: job.destination.clone(),
-            status: status,
-            writes: writes,
-            percentage: JobResponse::percentage(writes, job.source.to_owned(), config),
+            owner: job.owner,
+            priority: *job.priority.read().unwrap(),
+            status: JobResponse::status(&status),
+            writes: *job.writes.read().unwrap(),
+            files: *job.files.read().unwrap(),
+            bytes,
+            stored: *job.stored.read().unwrap(),
+            total,
+            percentage: JobResponse::percentage(&status, bytes, total),
+            digest: job.digest.read().unwrap().clone(),
+            resolution: job.resolution.read().unwrap().clone(),
+            method: *job.method.read().unwrap(),
+            attempts: *job.attempts.read().unwrap(),
+            created_at: timestamps.created,
+            started_at: timestamps.started,
+            finished_at: timestamps.finished,
+            throughput,
+            average_throughput,
+            eta,
         }
     }
 
-    fn percentage(writes: u64, source_path: String, config: Arc<RwLock<Config>>) -> f64 {
-        if let Ok(metadata) = fs::metadata(source_path) {
-            let source_bytes = metadata.len();
-            let buffer_size = config.read().unwrap().buffer_size as f64;
+    pub fn status(status: &JobStatus) -> String {
+        match status {
+            JobStatus::Failed(message) => format!("failed: {}", message),
+            JobStatus::Retrying(message) => format!("retrying: {}", message),
+            other => format!("{:?}", other),
+        }
+    }
 
-            let percentage = (writes as f64 * buffer_size) / source_bytes as f64;
+    // `status` is the name of a `JobStatus`, failures are reported as `failed: <reason>` and `retrying: <reason>`.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "Completed" | "Canceled" | "VerificationFailed" | "Skipped") || self.status.starts_with("failed")
+    }
 
-            if percentage > 1.0 {
-                1.0
-            } else {
-                percentage
-            }
-        } else {
-            0.0
-        }
+    // Whether the job did what it was asked to, a skipped job found its destination already in place.
+    pub fn is_successful(&self) -> bool {
+        matches!(self.status.as_str(), "Completed" | "Skipped")
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ProgressResponse {
-    pub job: JobResponse,
+    // A job with nothing to copy is done once it completes.
+    fn percentage(status: &JobStatus, bytes: u64, total: Option<u64>) -> f64 {
+        match total {
+            Some(total) if total > 0 => (bytes as f64 / total as f64).min(1.0),
+            _ if matches!(status, JobStatus::Completed | JobStatus::Skipped) => 1.0,
+            _ => 0.0,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use std::io::{self, Error, Read, Write};
+use std::mem;
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::models::job::Owner;
+
+// A connection accepted on one of the listeners.
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    // Applies to both reads and writes, None blocks forever.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)),
+            Stream::Unix(stream) => stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)),
+        }
+    }
+
+    // Who is on the other end, as reported by the kernel (SO_PEERCRED). Only known for Unix domain sockets.
+    pub fn peer(&self) -> io::Result<Option<Owner>> {
+        let stream = match self {
+            Stream::Tcp(_) => return Ok(None),
+            Stream::Unix(stream) => stream,
+        };
+
+        let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+
+        match result {
+            -1 => Err(Error::last_os_error()),
+            _ => Ok(Some(Owner { uid: credentials.uid, gid: credentials.gid })),
+        }
+    }
+
+    // Whether the other end hung up. Only peeks without blocking, whatever the client sent is left for the reader.
+    pub fn is_closed(&self) -> io::Result<bool> {
+        let fd = match self {
+            Stream::Tcp(stream) => stream.as_raw_fd(),
+            Stream::Unix(stream) => stream.as_raw_fd(),
+        };
+
+        let mut byte = 0u8;
+        let result = unsafe {
+            libc::recv(fd, &mut byte as *mut u8 as *mut libc::c_void, 1, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+        };
+
+        match result {
+            0 => Ok(true),
+            -1 => match Error::last_os_error() {
+                err if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+                err if matches!(err.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe) => Ok(true),
+                err => Err(err),
+            },
+            _ => Ok(false),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buffer),
+            Stream::Unix(stream) => stream.read(buffer),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buffer),
+            Stream::Unix(stream) => stream.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
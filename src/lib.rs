@@ -0,0 +1,6 @@
+// The daemon, its protocol and a blocking client for it. The `copy-service` and `csctl` binaries are built on top.
+pub mod client;
+pub mod models;
+pub mod services;
+
+pub use client::api::{ClientError, CopyServiceClient, Subscription};
\ No newline at end of file
@@ -1,26 +1,44 @@
 use std::{
-    fs::File,
+    env, fs::File, path::Path,
     process, sync::{mpsc::channel, Arc, RwLock, Mutex}, thread,
 };
+use clap::Parser;
 use daemonize::Daemonize;
 
-mod services;
-mod models;
-mod client;
+mod cli;
 
-use models::{config::Config, job::Job};
-use client::client::Client;
-use services::{storage::StorageService, copy::CopyService};
+use copy_service::models::config::Config;
+use copy_service::client::{client::Client, listener::Listener};
+use copy_service::services::{storage::StorageService, scheduler::Scheduler, copy::{Command, CopyService}, retention::RetentionService};
 
-fn run(config: Config) {
-    let (sender, receiver) = channel::<Job>();
+fn run(config: Config, listeners: Vec<Listener>) {
+    let (sender, receiver) = channel::<Command>();
     
-    let storage_service = Arc::new(RwLock::new(StorageService::new()));
-    let copy_service =  Arc::new(RwLock::new(CopyService::new(Arc::new(config.clone()), Mutex::new(receiver), storage_service.clone())));
-    let client_service = Arc::new(Mutex::new(Client::new(storage_service.clone(), sender,  Arc::new(RwLock::new(config.clone())))));
+    let storage_service = match StorageService::new(Path::new(&config.journal_file)) {
+        Ok(storage) => Arc::new(RwLock::new(storage)),
+        Err(err) => {
+            eprintln!("Error encountered when loading the job journal: {}", err);
+            process::exit(1);
+        }
+    };
+    if config.retention.is_enabled() {
+        let retention_service = RetentionService::new(config.retention.clone(), storage_service.clone());
+        thread::spawn(move || retention_service.execute());
+    }
+
+    let config = Arc::new(config);
+    let scheduler = Arc::new(RwLock::new(Scheduler::new()));
+    let copy_service =  Arc::new(RwLock::new(CopyService::new(
+        config.clone(),
+        sender.clone(),
+        Mutex::new(receiver),
+        scheduler.clone(),
+        storage_service.clone(),
+    )));
+    let client_service = Arc::new(Mutex::new(Client::new(config, storage_service.clone(), scheduler, sender)));
 
     let client_handle = thread::spawn(move || {
-        client_service.lock().unwrap().listen();
+        client_service.lock().unwrap().listen(listeners);
     });
 
     copy_service.write().unwrap().execute();
@@ -28,14 +46,39 @@ fn run(config: Config) {
 }
 
 fn main() {
-    let config = match Config::from_file("./Config.toml") {
+    let args = cli::Args::parse();
+
+    let config = match Config::load(&args.config, &args.overrides) {
         Ok(cfg) => cfg,
         Err(err) => {
-            eprintln!("Error encountered when reading/parsing config file: {}", err);
+            eprintln!("Error encountered when reading/parsing config file: {:#}", err);
+            process::exit(1);
+        }
+    };
+
+    if args.check_config {
+        println!("{} is valid", args.config);
+        return;
+    }
+
+    // bound before daemonizing, so that a listener that cannot be bound is reported right away
+    let listeners = match Listener::bind_all(&config) {
+        Ok(listeners) => listeners,
+        Err(err) => {
+            eprintln!("Error encountered when binding listeners: {:#}", err);
             process::exit(1);
         }
     };
 
+    if args.foreground {
+        if let Err(err) = env::set_current_dir(&config.working_directory) {
+            eprintln!("Error, could not change to {}: {}", config.working_directory, err);
+            process::exit(1);
+        }
+        run(config, listeners);
+        return;
+    }
+
     let stdout = File::create(&config.stdout_file).unwrap();
     let stderr = File::create(&config.stderr_file).unwrap();
 
@@ -46,7 +89,7 @@ fn main() {
         .stderr(stderr);
 
     match daemonize.start() {
-        Ok(_) => run(config),
+        Ok(_) => run(config, listeners),
         Err(err) => eprintln!("Error, {}", err),
     }
 }
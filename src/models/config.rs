@@ -1,23 +1,182 @@
+use std::fmt;
+use std::path::Path;
 use serde::Deserialize;
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+
+use crate::models::job::RetryPolicy;
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub pid_file: String,
     pub working_directory: String,
     pub stdout_file: String,
     pub stderr_file: String,
+    pub journal_file: String,
     
     pub buffer_size: usize,
     pub max_threads: usize,
+    pub checkpoint_interval: u64,
+
+    pub listeners: Vec<Endpoint>,
+    pub max_connections: usize,
+    pub idle_timeout: u64, // seconds a connection may stay silent before it is closed, 0 to never close it
+    #[serde(default)]
+    pub allow_anonymous_jobs: bool, // accept jobs from callers that cannot be identified (tcp) while running as root
 
     pub testing: bool, 
     pub delay: f32,
+
+    #[serde(default)]
+    pub retry: RetryPolicy, // for jobs that do not bring their own
+
+    #[serde(default)]
+    pub retention: Retention,
+}
+
+// How long finished jobs are kept. Without any limit they are kept forever.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    pub max_age: Option<u64>, // seconds since the job finished
+    pub max_count: Option<usize>, // finished jobs kept, the most recently finished ones
+    pub failed_max_age: Option<u64>, // seconds failed jobs are kept instead, they do not count towards max_count
+    pub interval: u64, // seconds between two sweeps
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention { max_age: None, max_count: None, failed_max_age: None, interval: 60 }
+    }
+}
+
+impl Retention {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_count.is_some() || self.failed_max_age.is_some()
+    }
+}
+
+// Where the daemon accepts client connections.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Endpoint {
+    Tcp {
+        host: String, // host name, IPv4 or IPv6 address
+        port: u16,
+    },
+    Unix {
+        path: String, // relative to working_directory, callers connecting through it are identified
+        mode: Option<u32>, // permissions of the socket file, e.g. 0o666
+    },
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { host, port } if host.contains(':') && !host.starts_with('[') => write!(f, "tcp [{}]:{}", host, port),
+            Endpoint::Tcp { host, port } => write!(f, "tcp {}:{}", host, port),
+            Endpoint::Unix { path, .. } => write!(f, "unix {}", path),
+        }
+    }
 }
 
 impl Config {
-    pub fn from_file(path: &str) -> Result<Self> {
-        let config_str = std::fs::read_to_string(path)?;
-        toml::from_str(&config_str).map_err(Into::into)
+    // Reads the config file, then replaces the fields named by `overrides`, each given as `field=value`.
+    // Values are TOML (`max_threads=8`, `listeners=[{type="tcp",host="::1",port=9000}]`), anything that
+    // does not parse as TOML is taken as a string (`journal_file=jobs.journal`). Fields of tables are named
+    // with dots (`retry.max_attempts=5`), a table given as a value only replaces the fields it names.
+    pub fn load(path: &str, overrides: &[String]) -> Result<Self> {
+        let config_str = std::fs::read_to_string(path).with_context(|| format!("Could not read {}", path))?;
+        let mut table: toml::Table = toml::from_str(&config_str).with_context(|| format!("Could not parse {}", path))?;
+
+        for parsed in overrides.iter().map(|o| Override::parse(o)).collect::<Result<Vec<_>>>()? {
+            parsed.apply(&mut table)?;
+        }
+
+        let config: Config = table.try_into().context("Invalid configuration")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Catches values that parse but that the daemon cannot run with.
+    pub fn validate(&self) -> Result<()> {
+        if self.max_threads == 0 {
+            return Err(anyhow!("max_threads has to be at least 1"));
+        }
+        if self.buffer_size == 0 {
+            return Err(anyhow!("buffer_size has to be at least 1"));
+        }
+        if self.checkpoint_interval == 0 {
+            return Err(anyhow!("checkpoint_interval has to be at least 1"));
+        }
+        if self.max_connections == 0 {
+            return Err(anyhow!("max_connections has to be at least 1"));
+        }
+        if self.listeners.is_empty() {
+            return Err(anyhow!("No listeners configured"));
+        }
+        if !self.delay.is_finite() || self.delay < 0.0 {
+            return Err(anyhow!("delay has to be a non-negative number of seconds"));
+        }
+        if let Err(message) = self.retry.validate() {
+            return Err(anyhow!("Invalid retry policy, {}", message));
+        }
+        if self.retention.interval == 0 {
+            return Err(anyhow!("retention.interval has to be at least 1"));
+        }
+        if !Path::new(&self.working_directory).is_dir() {
+            return Err(anyhow!("working_directory {} is not a directory", self.working_directory));
+        }
+        Ok(())
+    }
+}
+
+struct Override {
+    field: String,
+    value: toml::Value,
+}
+
+impl Override {
+    fn parse(raw: &str) -> Result<Self> {
+        let (field, value) = raw
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid override {}, expected field=value", raw))?;
+        let field = field.trim().to_string();
+        let value = value.trim();
+
+        let value = match format!("value = {}", value).parse::<toml::Table>() {
+            Ok(mut parsed) => parsed.remove("value").unwrap(),
+            Err(_) => toml::Value::String(value.to_string()),
+        };
+        Ok(Override { field, value })
+    }
+
+    fn apply(self, table: &mut toml::Table) -> Result<()> {
+        let mut keys: Vec<&str> = self.field.split('.').collect();
+        let last = keys.pop().unwrap_or_default();
+
+        let mut table = table;
+        for key in keys {
+            table = match table.entry(key).or_insert_with(|| toml::Value::Table(toml::Table::new())) {
+                toml::Value::Table(nested) => nested,
+                _ => return Err(anyhow!("Invalid override {}, {} is not a table", self.field, key)),
+            };
+        }
+        Override::merge(table, last, self.value);
+        Ok(())
+    }
+
+    // Tables are merged field by field into the tables they override, any other value replaces the old one.
+    fn merge(table: &mut toml::Table, key: &str, value: toml::Value) {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(fields)) => {
+                for (field, value) in fields {
+                    Override::merge(existing, &field, value);
+                }
+            }
+            (_, value) => {
+                table.insert(key.to_string(), value);
+            }
+        }
     }
 }
@@ -1,34 +1,431 @@
-use std::sync::{Arc, RwLock};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::models::throughput::Throughput;
+
 #[derive(Debug, Clone)]
 pub struct Job {
     pub id: Uuid,
+    pub seq: u64, // order in which the jobs were submitted, assigned once the job is stored
     pub source: String,
     pub destination: String,
+    pub options: JobOptions,
+    pub owner: Option<Owner>, // who submitted the job, unknown for jobs submitted over TCP
+    pub priority: Arc<RwLock<Priority>>, // queued jobs with a higher priority get a worker first
     pub status: Arc<RwLock<JobStatus>>,
     pub writes: Arc<RwLock<u64>>, // nr. of successful writes to the destination file
+    pub files: Arc<RwLock<u64>>, // nr. of regular files fully copied (directory jobs)
+    pub bytes: Arc<RwLock<u64>>, // nr. of bytes of the source copied so far (logical size), across all files
+    pub stored: Arc<RwLock<u64>>, // nr. of bytes actually written to the destination, holes excluded
+    pub offset: Arc<RwLock<u64>>, // offset in the file currently being copied known to be durable at the destination
+    pub digest: Arc<RwLock<Option<String>>>, // digest of the source, when the job is verified
+    pub resolution: Arc<RwLock<Option<Resolution>>>, // how an existing destination was dealt with, once decided
+    pub method: Arc<RwLock<Option<CopyStrategy>>>, // strategy that actually copied the last file
+    pub parked: Arc<RwLock<bool>>, // no worker holds the job, it has to be dispatched again to make progress
+    pub checkpointed: Arc<RwLock<Progress>>, // progress as of the last checkpoint, a retry resumes from there
+    pub attempts: Arc<RwLock<u32>>, // nr. of attempts made so far, the current one included
+    pub total: Arc<RwLock<Option<u64>>>, // size of the source, measured once when the job first starts
+    pub timestamps: Arc<RwLock<Timestamps>>,
+    pub throughput: Arc<Mutex<Throughput>>,
+}
+
+// Milliseconds since the Unix epoch, unknown for jobs recorded before timestamps were kept.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Timestamps {
+    pub created: Option<u64>,
+    pub started: Option<u64>, // first time the job ran
+    pub finished: Option<u64>,
+}
+
+impl Timestamps {
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+// Per-job settings chosen by the client when the job is submitted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<HashAlgorithm>,
+    #[serde(default)]
+    pub atomic: bool, // write to a temporary sibling and rename it over the destination once complete
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preserve: Vec<Preserve>,
+    #[serde(default)]
+    pub strategy: CopyStrategy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>, // overrides the daemon's retry policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>, // free-form labels to find the job by
+}
+
+// When and how often a job whose attempt failed is tried again. Attempts resume from the job's last checkpoint.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub max_attempts: u32, // 1 never retries
+    pub backoff: f64, // seconds to wait before the second attempt
+    pub multiplier: f64, // the wait grows by this factor with every attempt
+    pub max_backoff: f64, // seconds
+    pub retryable: Vec<ErrorClass>, // errors worth another attempt, any other error fails the job
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: 1.0,
+            multiplier: 2.0,
+            max_backoff: 300.0,
+            retryable: vec![
+                ErrorClass::NoSpace,
+                ErrorClass::WouldBlock,
+                ErrorClass::TimedOut,
+                ErrorClass::Interrupted,
+                ErrorClass::Busy,
+                ErrorClass::Stale,
+                ErrorClass::Network,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("max_attempts has to be at least 1".to_string());
+        }
+        if [self.backoff, self.max_backoff].iter().any(|seconds| !seconds.is_finite() || *seconds < 0.0) {
+            return Err("backoff and max_backoff have to be non-negative numbers of seconds".to_string());
+        }
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            return Err("multiplier has to be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    // How long to wait after the failed attempt nr. `attempt` (starting at 1).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        // kept finite, a backoff of 0 times an infinite factor would not be a number
+        let factor = self.multiplier.powi(attempt.saturating_sub(1).min(i32::MAX as u32) as i32).min(f64::MAX);
+        let seconds = self.backoff * factor;
+        Duration::from_secs_f64(seconds.min(self.max_backoff))
+    }
+}
+
+// Kinds of errors that may go away by themselves.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    NoSpace, // ENOSPC, EDQUOT
+    WouldBlock, // EAGAIN
+    TimedOut, // ETIMEDOUT
+    Interrupted, // EINTR
+    Busy, // EBUSY, ETXTBSY
+    Stale, // ESTALE, a file handle of a network filesystem went stale
+    Network, // the connection to a network filesystem's server broke
+    Io, // EIO
+}
+
+impl ErrorClass {
+    // Only system errors are classified, anything else (a missing source, a failed check, ...) is permanent.
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        let code = err.chain().find_map(|cause| cause.downcast_ref::<io::Error>())?.raw_os_error()?;
+        match code {
+            libc::ENOSPC | libc::EDQUOT => Some(ErrorClass::NoSpace),
+            libc::EAGAIN => Some(ErrorClass::WouldBlock),
+            libc::ETIMEDOUT => Some(ErrorClass::TimedOut),
+            libc::EINTR => Some(ErrorClass::Interrupted),
+            libc::EBUSY | libc::ETXTBSY => Some(ErrorClass::Busy),
+            libc::ESTALE => Some(ErrorClass::Stale),
+            libc::ENETDOWN | libc::ENETUNREACH | libc::EHOSTUNREACH | libc::ECONNRESET | libc::ECONNABORTED | libc::ENOTCONN =>
+                Some(ErrorClass::Network),
+            libc::EIO => Some(ErrorClass::Io),
+            _ => None,
+        }
+    }
+}
+
+// Progress counters of a job, as recorded by a checkpoint.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Progress {
+    pub writes: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub stored: u64,
+    pub offset: u64,
+}
+
+impl Progress {
+    pub fn of(job: &Job) -> Self {
+        Progress {
+            writes: *job.writes.read().unwrap(),
+            files: *job.files.read().unwrap(),
+            bytes: *job.bytes.read().unwrap(),
+            stored: *job.stored.read().unwrap(),
+            offset: *job.offset.read().unwrap(),
+        }
+    }
+}
+
+// Identity of a local user, as reported by the kernel for a Unix domain socket peer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+// How the bytes are moved. Strategies the kernel or the filesystems do not support fall back,
+// in this order, to the next one and eventually to the buffered read/write loop.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyStrategy {
+    #[default]
+    Auto,
+    Reflink, // FICLONE, copy-on-write filesystems only
+    CopyFileRange,
+    Sendfile,
+    Buffered,
+}
+
+impl CopyStrategy {
+    pub fn fallback(self) -> Self {
+        match self {
+            CopyStrategy::Auto | CopyStrategy::Reflink => CopyStrategy::CopyFileRange,
+            CopyStrategy::CopyFileRange => CopyStrategy::Sendfile,
+            CopyStrategy::Sendfile | CopyStrategy::Buffered => CopyStrategy::Buffered,
+        }
+    }
+}
+
+// Metadata of the source that is applied to the destination once the data is copied.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preserve {
+    Mode,
+    Timestamps, // atime and mtime
+    Ownership, // uid and gid, only when the daemon runs privileged
+    Xattrs,
+    Acls,
+}
+
+// What to do when the destination already exists.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    Fail,
+    Rename, // keep both, copy to an auto-suffixed name
+    Update, // overwrite only if the source is newer or has a different size
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Created, // the destination did not exist
+    Overwritten,
+    Skipped,
+    Renamed(String),
+    Updated,
+    UpToDate,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Crc32c,
+    Xxh3,
+    Sha256,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum JobStatus {
     Created,
+    Queued, // waiting for a worker
     Running,
     Suspended,
     Resumed, 
+    Verifying,
     Completed,
     Canceled,
     Failed(String),
+    Retrying(String), // the last attempt failed with this error, the next one starts after a backoff
+    VerificationFailed,
+    Skipped,
 }
 
 impl Job {
-    pub fn new(source: String, destination: String) -> Self {
+    pub fn new(source: String, destination: String, options: JobOptions) -> Self {
         Job {
             id: Uuid::new_v4(),
-            source: source, 
-            destination: destination,
+            seq: 0,
+            source,
+            destination,
+            options,
+            owner: None,
+            priority: Arc::new(RwLock::new(Priority::default())),
             status: Arc::new(RwLock::new(JobStatus::Created)),
             writes: Arc::new(RwLock::new(0u64)),
+            files: Arc::new(RwLock::new(0u64)),
+            bytes: Arc::new(RwLock::new(0u64)),
+            stored: Arc::new(RwLock::new(0u64)),
+            offset: Arc::new(RwLock::new(0u64)),
+            digest: Arc::new(RwLock::new(None)),
+            resolution: Arc::new(RwLock::new(None)),
+            method: Arc::new(RwLock::new(None)),
+            parked: Arc::new(RwLock::new(false)),
+            checkpointed: Arc::new(RwLock::new(Progress::default())),
+            attempts: Arc::new(RwLock::new(1)),
+            total: Arc::new(RwLock::new(None)),
+            timestamps: Arc::new(RwLock::new(Timestamps { created: Some(Timestamps::now()), ..Timestamps::default() })),
+            throughput: Arc::new(Mutex::new(Throughput::default())),
+        }
+    }
+
+    // Jobs with an owner are only accessible to that owner and to root, jobs without one to anybody.
+    pub fn is_accessible_by(&self, caller: Option<Owner>) -> bool {
+        match (self.owner, caller) {
+            (None, _) => true,
+            (Some(owner), Some(caller)) => caller.uid == owner.uid || caller.uid == 0,
+            (Some(_), None) => false,
+        }
+    }
+
+    // Changing a job takes more than seeing it: jobs without an owner can only be changed by callers without an
+    // identity of their own and by root, not by every user of the Unix socket.
+    pub fn is_controllable_by(&self, caller: Option<Owner>) -> bool {
+        match (self.owner, caller) {
+            (None, Some(caller)) => caller.uid == 0,
+            _ => self.is_accessible_by(caller),
+        }
+    }
+
+    // Where the data is copied to, which is not the requested destination if a conflict was resolved by renaming.
+    pub fn destination_path(&self) -> PathBuf {
+        match &*self.resolution.read().unwrap() {
+            Some(Resolution::Renamed(path)) => PathBuf::from(path),
+            _ => PathBuf::from(&self.destination),
+        }
+    }
+}
+
+impl JobStatus {
+    pub const NAMES: [&'static str; 12] = [
+        "Created", "Queued", "Running", "Suspended", "Resumed", "Verifying",
+        "Completed", "Canceled", "Failed", "Retrying", "VerificationFailed", "Skipped",
+    ];
+
+    // The variant's name, without the reason of a failure.
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Created => "Created",
+            JobStatus::Queued => "Queued",
+            JobStatus::Running => "Running",
+            JobStatus::Suspended => "Suspended",
+            JobStatus::Resumed => "Resumed",
+            JobStatus::Verifying => "Verifying",
+            JobStatus::Completed => "Completed",
+            JobStatus::Canceled => "Canceled",
+            JobStatus::Failed(_) => "Failed",
+            JobStatus::Retrying(_) => "Retrying",
+            JobStatus::VerificationFailed => "VerificationFailed",
+            JobStatus::Skipped => "Skipped",
         }
     }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Canceled | JobStatus::Failed(_) | JobStatus::VerificationFailed | JobStatus::Skipped)
+    }
+
+    // Finished without doing what it was asked to, kept longer by the retention policy.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, JobStatus::Failed(_) | JobStatus::VerificationFailed)
+    }
+
+    // Whether a worker is copying the job's data.
+    pub fn is_copying(&self) -> bool {
+        matches!(self, JobStatus::Running | JobStatus::Resumed)
+    }
+
+    // Whether the copy loop still checks on the job, verification runs to completion once started.
+    pub fn is_interruptible(&self) -> bool {
+        matches!(self, JobStatus::Created | JobStatus::Queued | JobStatus::Running | JobStatus::Resumed | JobStatus::Retrying(_))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(backoff: f64, multiplier: f64, max_backoff: f64) -> RetryPolicy {
+        RetryPolicy { max_attempts: 5, backoff, multiplier, max_backoff, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn delay_grows_by_the_multiplier_with_every_attempt() {
+        let policy = policy(1.5, 2.0, 300.0);
+        let delays: Vec<_> = (1..=4).map(|attempt| policy.delay(attempt)).collect();
+
+        assert_eq!(delays, [1.5, 3.0, 6.0, 12.0].map(Duration::from_secs_f64));
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_backoff() {
+        let policy = policy(1.0, 10.0, 60.0);
+
+        assert_eq!(policy.delay(2), Duration::from_secs(10));
+        assert_eq!(policy.delay(3), Duration::from_secs(60));
+        // far past the point where the factor overflows to infinity
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn delay_is_zero_without_a_backoff() {
+        let policy = policy(0.0, 2.0, 60.0);
+
+        assert_eq!(policy.delay(3), Duration::ZERO);
+        assert_eq!(policy.delay(u32::MAX), Duration::ZERO);
+    }
+
+    #[test]
+    fn delay_stays_constant_with_a_multiplier_of_one() {
+        let policy = policy(2.0, 1.0, 300.0);
+
+        assert_eq!(policy.delay(1), policy.delay(50));
+    }
+
+    #[test]
+    fn delay_of_attempt_zero_is_the_backoff() {
+        assert_eq!(policy(1.0, 2.0, 300.0).delay(0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn validate_rejects_policies_that_cannot_be_followed() {
+        assert!(RetryPolicy::default().validate().is_ok());
+        assert!(RetryPolicy { max_attempts: 0, ..RetryPolicy::default() }.validate().is_err());
+        assert!(policy(-1.0, 2.0, 300.0).validate().is_err());
+        assert!(policy(1.0, 0.5, 300.0).validate().is_err());
+        assert!(policy(1.0, 2.0, f64::INFINITY).validate().is_err());
+        assert!(policy(1.0, f64::NAN, 300.0).validate().is_err());
+    }
 }
@@ -1,2 +1,4 @@
 pub mod config;
-pub mod job;
\ No newline at end of file
+pub mod job;
+pub mod throughput;
+pub mod query;
\ No newline at end of file
@@ -0,0 +1,304 @@
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+use crate::models::job::{Job, JobStatus};
+
+// Which jobs a listing covers, in what order and how many at a time. Every filter is optional, a job has to
+// match all of the given ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobQuery {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub status: Vec<String>, // names of `JobStatus` variants, e.g. ["Running", "Failed"], in any case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_prefix: Option<String>, // matched a whole path component at a time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<u32>, // uid, jobs without an owner never match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<u64>, // milliseconds since the Unix epoch, inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<u64>, // exclusive
+
+    pub sort: SortField,
+    pub order: SortOrder,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>, // jobs per page, all of them if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>, // `next_cursor` of the previous page
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Created,
+    Started, // jobs that never started come first
+    Finished, // unfinished jobs come first
+    Priority,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// One page of the jobs matching a query.
+pub struct JobPage {
+    pub jobs: Vec<Arc<Job>>,
+    pub total: usize, // matching jobs on every page
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+// Where a page ends: the sort key and submission number of its last job, jobs with the same key are listed in the
+// order they were submitted. Jobs added or removed in between do not shift the pages.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct Cursor {
+    key: u64,
+    seq: u64,
+}
+
+impl Cursor {
+    fn parse(raw: &str) -> Option<Self> {
+        let (key, seq) = raw.split_once('.')?;
+        Some(Cursor { key: key.parse().ok()?, seq: seq.parse().ok()? })
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.key, self.seq)
+    }
+}
+
+impl JobQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = self.status.iter().find(|name| !JobStatus::NAMES.iter().any(|known| known.eq_ignore_ascii_case(name))) {
+            return Err(format!("unknown status {}, expected one of {}", name, JobStatus::NAMES.join(", ")));
+        }
+        if self.limit == Some(0) {
+            return Err("limit has to be at least 1".to_string());
+        }
+        if self.cursor.as_deref().is_some_and(|cursor| Cursor::parse(cursor).is_none()) {
+            return Err("invalid cursor".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, job: &Job) -> bool {
+        let created = job.timestamps.read().unwrap().created.unwrap_or(0);
+        let status = job.status.read().unwrap().name();
+
+        (self.status.is_empty() || self.status.iter().any(|name| name.eq_ignore_ascii_case(status)))
+            && self.source_prefix.as_ref().is_none_or(|prefix| Path::new(&job.source).starts_with(prefix))
+            && self.destination_prefix.as_ref().is_none_or(|prefix| Path::new(&job.destination).starts_with(prefix))
+            && self.owner.is_none_or(|uid| job.owner.is_some_and(|owner| owner.uid == uid))
+            && self.tag.as_ref().is_none_or(|tag| job.options.tags.contains(tag))
+            && self.created_after.is_none_or(|after| created >= after)
+            && self.created_before.is_none_or(|before| created < before)
+    }
+
+    // Sorts the matching jobs and cuts the page out of them, only the jobs on the page are cloned.
+    // The query has to be valid.
+    pub fn page<'a>(&self, jobs: impl Iterator<Item = &'a Arc<Job>>) -> JobPage {
+        let mut matching: Vec<(Cursor, &Arc<Job>)> = jobs.filter(|job| self.matches(job)).map(|job| (self.cursor_of(job), job)).collect();
+        matching.sort_unstable_by_key(|(cursor, _)| *cursor);
+        if self.order == SortOrder::Desc {
+            matching.reverse();
+        }
+
+        let start = match self.cursor.as_deref().and_then(Cursor::parse) {
+            Some(after) => match self.order {
+                SortOrder::Asc => matching.partition_point(|(cursor, _)| *cursor <= after),
+                SortOrder::Desc => matching.partition_point(|(cursor, _)| *cursor >= after),
+            },
+            None => 0,
+        };
+        let end = self.limit.map_or(matching.len(), |limit| start.saturating_add(limit).min(matching.len()));
+
+        JobPage {
+            jobs: matching[start..end].iter().map(|(_, job)| Arc::clone(job)).collect(),
+            total: matching.len(),
+            next_cursor: (end < matching.len()).then(|| matching[end - 1].0.to_string()),
+        }
+    }
+
+    fn cursor_of(&self, job: &Job) -> Cursor {
+        let timestamps = *job.timestamps.read().unwrap();
+        let key = match self.sort {
+            SortField::Created => timestamps.created.unwrap_or(0),
+            SortField::Started => timestamps.started.unwrap_or(0),
+            SortField::Finished => timestamps.finished.unwrap_or(0),
+            SortField::Priority => *job.priority.read().unwrap() as u64,
+        };
+        Cursor { key, seq: job.seq }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use uuid::Uuid;
+    use crate::models::job::{JobOptions, Owner, Priority};
+
+    static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+    // Numbered like the storage numbers the jobs it is given.
+    fn job(source: &str, created: u64) -> Arc<Job> {
+        let job = Job { seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed), ..Job::new(source.to_string(), "/backup".to_string(), JobOptions::default()) };
+        job.timestamps.write().unwrap().created = Some(created);
+        Arc::new(job)
+    }
+
+    // Every page of the query in turn, following the cursors.
+    fn pages(query: &JobQuery, jobs: &[Arc<Job>]) -> Vec<Vec<Uuid>> {
+        let mut query = query.clone();
+        let mut pages = Vec::new();
+        loop {
+            let page = query.page(jobs.iter());
+            assert_eq!(page.total, jobs.len());
+            pages.push(page.jobs.iter().map(|job| job.id).collect());
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return pages,
+            }
+        }
+    }
+
+    // Ids in the order the query sorts them in, ties on the key are broken by submission order.
+    fn sorted(jobs: &[Arc<Job>], order: SortOrder) -> Vec<Uuid> {
+        let mut jobs = jobs.to_vec();
+        jobs.sort_by_key(|job| (job.timestamps.read().unwrap().created, job.seq));
+        if order == SortOrder::Desc {
+            jobs.reverse();
+        }
+        jobs.iter().map(|job| job.id).collect()
+    }
+
+    #[test]
+    fn cursor_round_trips_through_its_string_form() {
+        let cursor = Cursor { key: 1_700_000_000_000, seq: 42 };
+
+        assert_eq!(Cursor::parse(&cursor.to_string()), Some(cursor));
+    }
+
+    #[test]
+    fn cursor_parse_rejects_malformed_cursors() {
+        let id = Uuid::new_v4();
+        for raw in ["", "12", "x.3", "-1.3", "12.-3", &format!("12.{}", id), "12.3.4"] {
+            assert_eq!(Cursor::parse(raw), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn ascending_pages_cover_every_job_once_in_order() {
+        let jobs: Vec<_> = [30, 10, 20, 20, 40, 10, 50].into_iter().map(|created| job("/data", created)).collect();
+        let query = JobQuery { limit: Some(2), ..JobQuery::default() };
+
+        let pages = pages(&query, &jobs);
+
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 2, 1]);
+        assert_eq!(pages.concat(), sorted(&jobs, SortOrder::Asc));
+    }
+
+    #[test]
+    fn descending_pages_cover_every_job_once_in_order() {
+        let jobs: Vec<_> = [30, 10, 20, 20, 40, 10, 50].into_iter().map(|created| job("/data", created)).collect();
+        let query = JobQuery { order: SortOrder::Desc, limit: Some(3), ..JobQuery::default() };
+
+        let pages = pages(&query, &jobs);
+
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [3, 3, 1]);
+        assert_eq!(pages.concat(), sorted(&jobs, SortOrder::Desc));
+    }
+
+    #[test]
+    fn jobs_created_at_the_same_time_are_listed_in_the_order_they_were_submitted() {
+        let jobs: Vec<_> = (0..20).map(|_| job("/data", 10)).collect();
+        let submitted: Vec<_> = jobs.iter().map(|job| job.id).collect();
+        let mut reversed = jobs.clone();
+        reversed.reverse();
+
+        let query = JobQuery { limit: Some(3), ..JobQuery::default() };
+
+        assert_eq!(pages(&query, &reversed).concat(), submitted);
+    }
+
+    #[test]
+    fn a_page_that_fits_exactly_has_no_next_cursor() {
+        let jobs: Vec<_> = [1, 2].into_iter().map(|created| job("/data", created)).collect();
+
+        let page = JobQuery { limit: Some(2), ..JobQuery::default() }.page(jobs.iter());
+
+        assert_eq!(page.jobs.len(), 2);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn jobs_added_before_the_cursor_do_not_shift_the_next_page() {
+        for order in [SortOrder::Asc, SortOrder::Desc] {
+            let mut jobs: Vec<_> = [10, 20, 30, 40].into_iter().map(|created| job("/data", created)).collect();
+            let query = JobQuery { order, limit: Some(2), ..JobQuery::default() };
+            let first = query.page(jobs.iter());
+            let expected = sorted(&jobs, order)[2..].to_vec();
+
+            // lands on the first page's side of the cursor, whichever the order
+            jobs.push(job("/data", if order == SortOrder::Asc { 5 } else { 45 }));
+            let next = JobQuery { cursor: first.next_cursor, ..query }.page(jobs.iter());
+
+            assert_eq!(next.jobs.iter().map(|job| job.id).collect::<Vec<_>>(), expected);
+            assert_eq!(next.total, 5);
+        }
+    }
+
+    #[test]
+    fn matches_applies_every_given_filter() {
+        let data = job("/data/a", 100);
+        let database = job("/database/b", 200);
+        *database.status.write().unwrap() = JobStatus::Failed("disk full".to_string());
+        let owned = Arc::new(Job {
+            owner: Some(Owner { uid: 1000, gid: 1000 }),
+            ..Job::new("/data/c".to_string(), "/backup".to_string(), JobOptions { tags: vec!["nightly".to_string()], ..JobOptions::default() })
+        });
+        owned.timestamps.write().unwrap().created = Some(300);
+        let jobs = [data.clone(), database.clone(), owned.clone()];
+
+        let ids = |query: JobQuery| query.page(jobs.iter()).jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+        assert_eq!(ids(JobQuery { source_prefix: Some("/data".to_string()), ..JobQuery::default() }), [data.id, owned.id]);
+        assert_eq!(ids(JobQuery { status: vec!["failed".to_string()], ..JobQuery::default() }), [database.id]);
+        assert_eq!(ids(JobQuery { owner: Some(1000), ..JobQuery::default() }), [owned.id]);
+        assert_eq!(ids(JobQuery { tag: Some("nightly".to_string()), ..JobQuery::default() }), [owned.id]);
+        assert_eq!(ids(JobQuery { created_after: Some(200), created_before: Some(300), ..JobQuery::default() }), [database.id]);
+        assert!(ids(JobQuery { source_prefix: Some("/data".to_string()), status: vec!["failed".to_string()], ..JobQuery::default() }).is_empty());
+    }
+
+    #[test]
+    fn sorting_by_priority_puts_urgent_jobs_last_in_ascending_order() {
+        let (low, urgent) = (job("/a", 1), job("/b", 2));
+        *low.priority.write().unwrap() = Priority::Low;
+        *urgent.priority.write().unwrap() = Priority::Urgent;
+        let jobs = [urgent.clone(), low.clone()];
+
+        let page = JobQuery { sort: SortField::Priority, ..JobQuery::default() }.page(jobs.iter());
+
+        assert_eq!(page.jobs.iter().map(|job| job.id).collect::<Vec<_>>(), [low.id, urgent.id]);
+    }
+
+    #[test]
+    fn validate_rejects_unknown_statuses_empty_pages_and_bad_cursors() {
+        assert!(JobQuery { status: vec!["RUNNING".to_string()], ..JobQuery::default() }.validate().is_ok());
+        assert!(JobQuery { status: vec!["sleeping".to_string()], ..JobQuery::default() }.validate().is_err());
+        assert!(JobQuery { limit: Some(0), ..JobQuery::default() }.validate().is_err());
+        assert!(JobQuery { cursor: Some("nope".to_string()), ..JobQuery::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Current throughput is measured over this much of the recent past.
+const WINDOW: Duration = Duration::from_secs(5);
+const SAMPLE_SPACING: Duration = Duration::from_millis(200);
+// Rates measured over less time than this are too noisy to report.
+const MIN_ELAPSED: Duration = Duration::from_millis(100);
+
+// Measures how fast a job copies while it is running. Only time spent by this daemon counts,
+// progress made before a restart is not measured.
+#[derive(Debug, Default)]
+pub struct Throughput {
+    samples: VecDeque<(Instant, u64)>, // bytes copied at recent points in time, the newest last
+    since: Option<Instant>, // when the job last started copying, None while it is not
+    baseline: u64, // bytes copied when the job last started copying
+    active: Duration, // time spent copying before `since`
+    copied: u64, // bytes copied during `active`
+}
+
+impl Throughput {
+    pub fn start(&mut self, bytes: u64) {
+        if self.since.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.since = Some(now);
+        self.baseline = bytes;
+        self.samples = VecDeque::from([(now, bytes)]);
+    }
+
+    pub fn stop(&mut self, bytes: u64) {
+        if let Some(since) = self.since.take() {
+            self.active += since.elapsed();
+            self.copied += bytes.saturating_sub(self.baseline);
+            self.samples.clear();
+        }
+    }
+
+    pub fn record(&mut self, bytes: u64) {
+        let now = Instant::now();
+        if self.since.is_none() || self.samples.back().is_some_and(|(at, _)| now.duration_since(*at) < SAMPLE_SPACING) {
+            return;
+        }
+
+        self.samples.push_back((now, bytes));
+        // the oldest sample kept is the first one inside the window, or right before it
+        while self.samples.get(1).is_some_and(|(at, _)| now.duration_since(*at) >= WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+
+    // Bytes per second over the last few seconds, None unless the job is copying.
+    pub fn current(&self, bytes: u64) -> Option<f64> {
+        self.since?;
+        let (at, oldest) = self.samples.front()?;
+        Throughput::rate(bytes.saturating_sub(*oldest), at.elapsed())
+    }
+
+    // Bytes per second over all the time the job spent copying.
+    pub fn average(&self, bytes: u64) -> Option<f64> {
+        let (elapsed, copied) = match self.since {
+            Some(since) => (self.active + since.elapsed(), self.copied + bytes.saturating_sub(self.baseline)),
+            None => (self.active, self.copied),
+        };
+        Throughput::rate(copied, elapsed)
+    }
+
+    fn rate(bytes: u64, elapsed: Duration) -> Option<f64> {
+        (elapsed >= MIN_ELAPSED).then(|| bytes as f64 / elapsed.as_secs_f64())
+    }
+}
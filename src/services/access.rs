@@ -0,0 +1,202 @@
+use std::fs;
+use std::io::{self, Error};
+use std::mem;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::models::job::{Job, Owner};
+use crate::services::tree;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    Search, // traversing a directory
+}
+
+impl Access {
+    fn bit(self) -> u32 {
+        match self {
+            Access::Read => 0o4,
+            Access::Write => 0o2,
+            Access::Search => 0o1,
+        }
+    }
+}
+
+// A local user along with every group it belongs to.
+pub struct Identity {
+    uid: u32,
+    gid: u32,
+    groups: Vec<libc::gid_t>,
+}
+
+impl Identity {
+    // Users missing from the user database only get their primary group.
+    pub fn of(owner: Owner) -> Self {
+        Identity {
+            uid: owner.uid,
+            gid: owner.gid,
+            groups: supplementary_groups(owner).unwrap_or_else(|| vec![owner.gid]),
+        }
+    }
+
+    // Mirrors the kernel's check of the permission bits, ACLs are not taken into account.
+    pub fn can(&self, path: &Path, access: Access) -> io::Result<bool> {
+        if self.uid == 0 {
+            return Ok(true);
+        }
+
+        let meta = fs::metadata(path)?;
+        let mode = if meta.uid() == self.uid {
+            meta.mode() >> 6
+        } else if meta.gid() == self.gid || self.groups.contains(&meta.gid()) {
+            meta.mode() >> 3
+        } else {
+            meta.mode()
+        };
+
+        Ok(mode & access.bit() != 0)
+    }
+
+    fn require(&self, path: &Path, access: Access) -> Result<(), String> {
+        match self.can(path, access) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("Permission denied, user {} has no {:?} access to {}", self.uid, access, path.display())),
+            Err(err) => Err(format!("Could not check access to {}: {}", path.display(), err)),
+        }
+    }
+
+    // Every directory leading to `path` has to be searchable.
+    fn require_reachable(&self, path: &Path) -> Result<(), String> {
+        for ancestor in path.ancestors().skip(1).filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            self.require(ancestor, Access::Search)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks that the job's owner could copy the source to the destination by themselves: the whole source has to
+// be readable and the destination directory writable.
+pub fn check(owner: Owner, job: &Job) -> Result<(), String> {
+    let identity = Identity::of(owner);
+    let source = absolute(Path::new(&job.source));
+    let destination = absolute(&job.destination_path());
+
+    identity.require_reachable(&source)?;
+    identity.require(&source, Access::Read)?;
+    if source.is_dir() {
+        identity.require(&source, Access::Search)?;
+
+        let entries = tree::walk(&source).map_err(|err| err.to_string())?;
+        for entry in entries {
+            let path = source.join(&entry.path);
+            identity.require(&path, Access::Read)?;
+            if path.is_dir() {
+                identity.require(&path, Access::Search)?;
+            }
+        }
+    }
+
+    // the destination may not exist yet, neither may some of its parent directories
+    let directory = destination
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    identity.require_reachable(&destination)?;
+    identity.require(directory, Access::Write)?;
+    identity.require(directory, Access::Search)?;
+    if destination.exists() {
+        identity.require(&destination, Access::Write)?;
+    }
+
+    Ok(())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn supplementary_groups(owner: Owner) -> Option<Vec<libc::gid_t>> {
+    let mut buffer = vec![0 as libc::c_char; 16384];
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut found: *mut libc::passwd = ptr::null_mut();
+    let result = unsafe { libc::getpwuid_r(owner.uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut found) };
+    if result != 0 || found.is_null() {
+        return None;
+    }
+
+    let mut count: libc::c_int = 32;
+    loop {
+        let mut groups = vec![0 as libc::gid_t; count as usize];
+        let capacity = count;
+        if unsafe { libc::getgrouplist(passwd.pw_name, owner.gid, groups.as_mut_ptr(), &mut count) } != -1 {
+            groups.truncate(count as usize);
+            return Some(groups);
+        }
+        // `count` now holds the nr. of groups the user is in
+        count = count.max(capacity * 2);
+    }
+}
+
+// Whether the daemon runs as root. Jobs without an owner are then copied with root's permissions.
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+// Whether the calling thread accesses files as root. False while it impersonates a job's owner.
+pub fn is_privileged() -> bool {
+    // an invalid id leaves the filesystem uid alone and returns the current one
+    unsafe { libc::setfsuid(libc::uid_t::MAX) == 0 }
+}
+
+// While alive, the calling thread accesses files with the uid, gid and groups of a job's owner, so that the kernel
+// enforces the owner's permissions. Dropping it restores the daemon's own identity.
+pub struct Impersonation {
+    groups: Vec<libc::gid_t>,
+}
+
+impl Impersonation {
+    // Only a daemon running as root can impersonate anybody, otherwise the checks in `check` are all there is.
+    pub fn start(owner: Owner) -> io::Result<Option<Self>> {
+        if unsafe { libc::geteuid() } != 0 || owner.uid == 0 {
+            return Ok(None);
+        }
+
+        let count = unsafe { libc::getgroups(0, ptr::null_mut()) };
+        let mut groups = vec![0 as libc::gid_t; count.max(0) as usize];
+        if unsafe { libc::getgroups(count, groups.as_mut_ptr()) } == -1 {
+            return Err(Error::last_os_error());
+        }
+
+        let impersonation = Impersonation { groups };
+        set_thread_groups(&Identity::of(owner).groups)?;
+        unsafe {
+            libc::setfsgid(owner.gid);
+            libc::setfsuid(owner.uid);
+        }
+        Ok(Some(impersonation))
+    }
+}
+
+impl Drop for Impersonation {
+    fn drop(&mut self) {
+        unsafe {
+            libc::setfsuid(libc::geteuid());
+            libc::setfsgid(libc::getegid());
+        }
+        if let Err(err) = set_thread_groups(&self.groups) {
+            eprintln!("Error restoring supplementary groups: {}", err);
+        }
+    }
+}
+
+// The libc wrapper changes the groups of every thread of the process, the system call only those of the caller.
+fn set_thread_groups(groups: &[libc::gid_t]) -> io::Result<()> {
+    match unsafe { libc::syscall(libc::SYS_setgroups, groups.len(), groups.as_ptr()) } {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
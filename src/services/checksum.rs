@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::models::job::HashAlgorithm;
+use crate::services::tree::{self, EntryKind};
+
+pub enum Hasher {
+    Crc32c(u32),
+    Xxh3(Box<Xxh3>),
+    Sha256(Box<Sha256>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Crc32c => Hasher::Crc32c(0),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            Hasher::Xxh3(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn update_zeros(&mut self, mut len: u64) {
+        let zeros = [0u8; 4096];
+        while len > 0 {
+            let chunk = len.min(zeros.len() as u64) as usize;
+            self.update(&zeros[..chunk]);
+            len -= chunk as u64;
+        }
+    }
+
+    // Files of a directory tree are hashed as their path relative to the root followed by their contents,
+    // so that renamed or moved files change the digest as well.
+    pub fn update_path(&mut self, relative: &Path) {
+        self.update(relative.to_string_lossy().as_bytes());
+        self.update(&[0]);
+    }
+
+    // Hashes at most `limit` bytes from the start of the file at `path`.
+    pub fn update_from_file(&mut self, path: &Path, limit: u64) -> Result<()> {
+        self.update_from_reader(BufReader::new(File::open(path)?).take(limit))
+    }
+
+    pub fn update_from_reader(&mut self, mut reader: impl Read) -> Result<()> {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => return Ok(()),
+                bytes_read => self.update(&buffer[..bytes_read]),
+            }
+        }
+    }
+
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Crc32c(crc) => format!("{:08x}", crc),
+            Hasher::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+            Hasher::Sha256(hasher) => hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+// Digest of `path` as a whole, hashing directory trees the same way a copy job does while streaming.
+// `layout` is the tree whose file listing is used, so that a destination is hashed with the source's files.
+pub fn digest(algorithm: HashAlgorithm, path: &Path, layout: &Path) -> Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+
+    if layout.is_dir() {
+        for entry in tree::walk(layout)?.iter().filter(|entry| entry.kind == EntryKind::File) {
+            hasher.update_path(&entry.path);
+            hasher.update_from_file(&path.join(&entry.path), u64::MAX)?;
+        }
+    } else {
+        hasher.update_from_file(path, u64::MAX)?;
+    }
+
+    Ok(hasher.finalize())
+}
@@ -1,117 +1,638 @@
-use std::io::{Read, Seek, SeekFrom, Write, BufWriter, BufReader};
-use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write, BufWriter, BufReader};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, Mutex};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 use anyhow::{Result, anyhow};
 use threadpool::ThreadPool;
 use std::time::Duration;
 use std::thread;
 
 use crate::services::storage::StorageService;
-use crate::models::job::{Job, JobStatus};
+use crate::services::scheduler::Scheduler;
+use crate::models::job::{ConflictPolicy, CopyStrategy, ErrorClass, HashAlgorithm, Job, JobStatus, Resolution};
 use crate::models::config::Config;
-use crate::services::validate::validate;
+use crate::services::validate::{is_outdated, resolve_conflict, validate};
+use crate::services::tree::{self, EntryKind};
+use crate::services::checksum::{self, Hasher};
+use crate::services::metadata;
+use crate::services::kernel;
+use crate::services::access::Impersonation;
+
+// Messages handled by the dispatcher.
+pub enum Command {
+    Dispatch(Arc<Job>), // a recorded job that needs a worker, a new or a resumed job
+    Release, // a worker is done with its job and can take the next queued one
+}
+
+// How far a copy got before it returned.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Outcome {
+    Finished,
+    Suspended, // the job was parked, whatever was copied is kept to resume from
+    Canceled,
+}
 
 pub struct CopyService {
     config: Arc<Config>,
     storage: Arc<RwLock<StorageService>>,
-    receiver: Mutex<Receiver<Job>>,
+    scheduler: Arc<RwLock<Scheduler>>,
+    sender: Sender<Command>,
+    receiver: Mutex<Receiver<Command>>,
     workers: ThreadPool,
+    busy_workers: usize,
 }
 
 impl CopyService {
-    pub fn new(config: Arc<Config>, receiver: Mutex<Receiver<Job>>, storage: Arc<RwLock<StorageService>>) -> Self {
-        let workers = ThreadPool::new(config.max_threads as usize);
+    pub fn new(
+        config: Arc<Config>,
+        sender: Sender<Command>,
+        receiver: Mutex<Receiver<Command>>,
+        scheduler: Arc<RwLock<Scheduler>>,
+        storage: Arc<RwLock<StorageService>>,
+    ) -> Self {
+        let workers = ThreadPool::new(config.max_threads);
 
         CopyService {
-            config: config,
-            receiver: receiver,
-            storage: storage,
-            workers: workers, 
+            config,
+            storage,
+            scheduler,
+            sender,
+            receiver,
+            workers,
+            busy_workers: 0,
         }
     }
 
     pub fn execute(&mut self) {
+        let unfinished_jobs = self.storage.read().unwrap().unfinished_jobs();
+        for job in unfinished_jobs {
+            self.enqueue(job);
+        }
+        self.dispatch_queued();
+
+        // blocks until the next command, the service keeps a sender of its own so this runs as long as the daemon
         loop {
-            if let Ok(data) = self.receiver.lock().unwrap().try_recv() {
-                let job = self.storage.write().unwrap().add_job(data);
-                let config_clone = Arc::clone(&self.config);
+            let received = self.receiver.lock().unwrap().recv();
+            match received {
+                Ok(Command::Dispatch(job)) => self.enqueue(job),
+                Ok(Command::Release) => self.busy_workers -= 1,
+                Err(_) => break,
+            }
+            self.dispatch_queued();
+        }
+    }
 
-                self.workers.execute(move || {
-                    if let Err(err) = CopyService::execute_job(&config_clone, job.clone()) {
-                        StorageService::update_job_status(job, JobStatus::Failed(err.to_string()));
-                    }
+    // Suspended jobs are parked rather than queued. Canceled jobs are still queued, a worker cleans up after them.
+    fn enqueue(&self, job: Arc<Job>) {
+        let storage = self.storage.read().unwrap();
+        if StorageService::park_job(job.clone()) {
+            return;
+        }
+
+        storage.transition_job(job.clone(), JobStatus::is_interruptible, JobStatus::Queued);
+        self.scheduler.write().unwrap().push(job);
+    }
+
+    // Hands queued jobs to idle workers, the thread pool itself never has to queue anything.
+    fn dispatch_queued(&mut self) {
+        while self.busy_workers < self.config.max_threads {
+            let job = match self.scheduler.write().unwrap().pop() {
+                Some(job) => job,
+                None => break,
+            };
+
+            // suspended while it was queued
+            if StorageService::park_job(job.clone()) {
+                continue;
+            }
+
+            self.busy_workers += 1;
+            self.dispatch(job);
+        }
+    }
+
+    fn dispatch(&self, job: Arc<Job>) {
+        let config_clone = Arc::clone(&self.config);
+        let storage_clone = Arc::clone(&self.storage);
+        let sender_clone = self.sender.clone();
+
+        self.workers.execute(move || {
+            if let Err(err) = CopyService::execute_job(&config_clone, &storage_clone, job.clone()) {
+                CopyService::fail(&config_clone, &storage_clone, &sender_clone, job.clone(), err);
+            }
+            if StorageService::release_job(job.clone()) {
+                StorageService::restore_checkpoint(job.clone());
+                let _ = sender_clone.send(Command::Dispatch(job));
+            }
+            let _ = sender_clone.send(Command::Release);
+        });
+    }
+
+    // Schedules the job's next attempt if its retry policy allows for one, fails it otherwise.
+    // The worker is released right away, the job is dispatched again once its backoff elapsed.
+    fn fail(config: &Arc<Config>, storage: &Arc<RwLock<StorageService>>, sender: &Sender<Command>, job: Arc<Job>, err: anyhow::Error) {
+        let message = err.to_string();
+        if let Some(delay) = CopyService::retry_delay(config, &job, &err) {
+            eprintln!("Job {}: attempt {} failed, retrying in {:?}: {}", job.id, job.attempts.read().unwrap(), delay, message);
+            StorageService::restore_checkpoint(job.clone());
+            if storage.read().unwrap().retry_job(job.clone(), message.clone()) {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    let _ = sender.send(Command::Dispatch(job));
                 });
+                return;
+            }
+
+            // suspended meanwhile, resuming it starts the next attempt
+            if StorageService::park_job(job.clone()) {
+                return;
             }
         }
+
+        // canceled meanwhile, the job stays canceled and whatever was kept for the next attempt goes
+        if storage.read().unwrap().transition_job(job.clone(), |status| *status != JobStatus::Canceled, JobStatus::Failed(message)).is_none() {
+            CopyService::clean_up(storage, job);
+        }
+    }
+
+    // How long to wait before retrying the job after `err`, None if the error is permanent or no attempts are left.
+    fn retry_delay(config: &Config, job: &Job, err: &anyhow::Error) -> Option<Duration> {
+        let policy = job.options.retry.as_ref().unwrap_or(&config.retry);
+        let attempts = *job.attempts.read().unwrap();
+        let class = ErrorClass::of(err)?;
+
+        (attempts < policy.max_attempts && policy.retryable.contains(&class)).then(|| policy.delay(attempts))
     }
     
-    fn execute_job(config: &Arc<Config>, job: Arc<Job>) -> Result<Arc<Job>> {
-        StorageService::update_job_status(job.clone(), JobStatus::Running);
+    fn execute_job(config: &Arc<Config>, storage: &Arc<RwLock<StorageService>>, job: Arc<Job>) -> Result<Arc<Job>> {
+        // a canceled job is not copied any further, its source may well be gone by now
+        if *job.status.read().unwrap() == JobStatus::Canceled {
+            // one canceled before it ever got a worker has nothing to clean up
+            if job.resolution.read().unwrap().is_some() {
+                CopyService::clean_up(storage, job.clone());
+            }
+            return Ok(job.clone());
+        }
+
+        // from here on the kernel enforces the permissions of the job's owner
+        let _impersonation = match job.owner {
+            Some(owner) => Impersonation::start(owner)?,
+            None => None,
+        };
+
+        // jobs may have been suspended or canceled since they were queued, the copy loop deals with those
+        storage.read().unwrap().transition_job(job.clone(), JobStatus::is_interruptible, JobStatus::Running);
+
         if let(false, message) = validate(job.clone()) {
             return Err(anyhow!(message));
         }
 
-        let mut source = CopyService::source_reader(config, job.clone())?;
-        let mut destination = CopyService::destination_writer(&job.clone())?;
-        
-        let mut buffer: Vec<u8> = vec![0; config.buffer_size.clone()];
-        while let Ok(bytes_read) = source.read(&mut buffer) {
-            if bytes_read == 0 {
-                StorageService::update_job_status(job.clone(), JobStatus::Completed);
+        // decided only once, a resumed job must not mistake its own partial copy for a conflict
+        if job.resolution.read().unwrap().is_none() {
+            let resolution = resolve_conflict(job.clone()).map_err(|message| anyhow!(message))?;
+            storage.read().unwrap().update_job_resolution(job.clone(), resolution);
+        }
+
+        if matches!(*job.resolution.read().unwrap(), Some(Resolution::Skipped | Resolution::UpToDate)) {
+            storage.read().unwrap().update_job_status(job.clone(), JobStatus::Skipped);
+            return Ok(job.clone());
+        }
+
+        let source_path = Path::new(&job.source);
+        let destination_path = &job.destination_path();
+        let is_tree = source_path.is_dir();
+
+        // measured once, progress is reported against the source as it was when the job started
+        if job.total.read().unwrap().is_none() {
+            storage.read().unwrap().update_job_total(job.clone(), tree::size(source_path)?);
+        }
+
+        let mut hasher = job.options.verify.map(Hasher::new);
+        let outcome = if is_tree {
+            CopyService::copy_tree(config, storage, job.clone(), &mut hasher)?
+        } else {
+            CopyService::copy_entry(config, storage, job.clone(), source_path, destination_path, &mut hasher)?
+        };
+
+        if outcome != Outcome::Finished {
+            return Ok(job.clone());
+        }
+
+        // files of a tree are already in place, a single file is only moved into place once verified
+        let written_path = if is_tree { destination_path.to_path_buf() } else { CopyService::staging_path(&job, destination_path) };
+        let finalized = CopyService::finalize(storage, job.clone(), hasher, &written_path);
+
+        if !is_tree && matches!(finalized, Ok(true)) {
+            CopyService::commit(&job, destination_path)?;
+        } else if !is_tree {
+            CopyService::discard(storage, job.clone(), destination_path);
+        }
+
+        let verified = finalized?;
+        let status = if verified { JobStatus::Completed } else { JobStatus::VerificationFailed };
+        // the last checkpoint may predate the last writes, the job is reported in full after a restart too
+        storage.read().unwrap().checkpoint_job(job.clone());
+        storage.read().unwrap().update_job_status(job.clone(), status);
+
+        Ok(job.clone())
+    }
+
+    // Verifies the copy at `written_path` and applies the preserved metadata to it.
+    // Returns false if verification failed, in which case the metadata is left alone.
+    fn finalize(storage: &Arc<RwLock<StorageService>>, job: Arc<Job>, hasher: Option<Hasher>, written_path: &Path) -> Result<bool> {
+        let verified = match (job.options.verify, hasher) {
+            (Some(algorithm), Some(hasher)) => CopyService::verify(storage, job.clone(), algorithm, hasher, written_path)?,
+            _ => true,
+        };
+
+        if verified && !job.options.preserve.is_empty() {
+            let source_path = Path::new(&job.source);
+            if source_path.is_dir() {
+                metadata::apply_tree(&job.options.preserve, source_path, written_path)?;
+            } else {
+                metadata::apply(&job.options.preserve, source_path, written_path)?;
+            }
+        }
+
+        Ok(verified)
+    }
+
+    // Re-reads the copy at `path` and compares its digest with the one computed while streaming the source.
+    fn verify(storage: &Arc<RwLock<StorageService>>, job: Arc<Job>, algorithm: HashAlgorithm, hasher: Hasher, path: &Path) -> Result<bool> {
+        storage.read().unwrap().update_job_status(job.clone(), JobStatus::Verifying);
+
+        let source_digest = hasher.finalize();
+        storage.read().unwrap().update_job_digest(job.clone(), source_digest.clone());
+
+        let destination_digest = checksum::digest(algorithm, path, Path::new(&job.source))?;
+        if source_digest != destination_digest {
+            eprintln!("Job {}: source digest {} does not match destination digest {}", job.id, source_digest, destination_digest);
+        }
+
+        Ok(source_digest == destination_digest)
+    }
+
+    // The hasher only sees the bytes streamed by this run, feed it whatever an earlier run already copied.
+    fn catch_up(job: Arc<Job>, hasher: &mut Option<Hasher>, source_path: &Path) -> Result<()> {
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update_from_file(source_path, *job.offset.read().unwrap())?;
+        }
+        Ok(())
+    }
+
+    // Recreates the directory structure of the source at the destination and copies every
+    // regular file in it, unless the job is interrupted before the whole tree was copied.
+    fn copy_tree(config: &Arc<Config>, storage: &Arc<RwLock<StorageService>>, job: Arc<Job>, hasher: &mut Option<Hasher>) -> Result<Outcome> {
+        let source_root = Path::new(&job.source);
+        let destination_root = &job.destination_path();
+        fs::create_dir_all(destination_root)?;
+
+        // files copied before the daemon was restarted, the walk order is stable
+        let copied_files = *job.files.read().unwrap();
+        let mut skipped_files = 0;
+
+        for entry in tree::walk(source_root)? {
+            let source_path = source_root.join(&entry.path);
+            let destination_path = destination_root.join(&entry.path);
+
+            if entry.kind == EntryKind::File {
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update_path(&entry.path);
+                }
+            }
+
+            match entry.kind {
+                EntryKind::Directory => fs::create_dir_all(&destination_path)?,
+                EntryKind::File if skipped_files < copied_files => {
+                    if let Some(hasher) = hasher.as_mut() {
+                        hasher.update_from_file(&source_path, u64::MAX)?;
+                    }
+                    skipped_files += 1;
+                }
+                EntryKind::File if job.options.conflict == ConflictPolicy::Update && !is_outdated(&source_path, &destination_path) => {
+                    if let Some(hasher) = hasher.as_mut() {
+                        hasher.update_from_file(&source_path, u64::MAX)?;
+                    }
+                    StorageService::skip_job_file(job.clone(), fs::metadata(&source_path)?.len());
+                    storage.read().unwrap().checkpoint_job(job.clone());
+                }
+                EntryKind::File => {
+                    let outcome = CopyService::copy_entry(config, storage, job.clone(), &source_path, &destination_path, hasher)?;
+                    if outcome != Outcome::Finished {
+                        return Ok(outcome);
+                    }
+                    CopyService::commit(&job, &destination_path)?;
+
+                    StorageService::increment_job_files(job.clone());
+                    storage.read().unwrap().checkpoint_job(job.clone());
+                }
+            }
+        }
+
+        Ok(Outcome::Finished)
+    }
+
+    // Copies one regular file into its staging path, resuming from the job's durable offset.
+    // The staging file of an atomic job is dropped if the copy is canceled or fails for good.
+    fn copy_entry(
+        config: &Arc<Config>,
+        storage: &Arc<RwLock<StorageService>>,
+        job: Arc<Job>,
+        source_path: &Path,
+        destination_path: &Path,
+        hasher: &mut Option<Hasher>,
+    ) -> Result<Outcome> {
+        let staging_path = CopyService::staging_path(&job, destination_path);
+
+        let copied = CopyService::catch_up(job.clone(), hasher, source_path).and_then(|_| {
+            let source = CopyService::source_reader(job.clone(), source_path)?;
+            let destination = CopyService::destination_writer(job.clone(), &staging_path)?;
+            CopyService::copy_file(config, storage, job.clone(), source, destination, hasher)
+        });
+
+        let retried = matches!(&copied, Err(err) if CopyService::retry_delay(config, &job, err).is_some());
+        if matches!(copied, Ok(Outcome::Canceled) | Err(_)) && !retried {
+            CopyService::discard(storage, job.clone(), destination_path);
+        }
+        copied
+    }
+
+    // Atomic jobs write into a hidden sibling of the destination named after the job, so that an interrupted
+    // copy can be resumed from it and readers never see a partially written destination.
+    fn staging_path(job: &Arc<Job>, destination_path: &Path) -> PathBuf {
+        if !job.options.atomic {
+            return destination_path.to_path_buf();
+        }
+
+        let name = destination_path.file_name().unwrap_or_default().to_string_lossy();
+        destination_path.with_file_name(format!(".{}.{}.tmp", name, job.id))
+    }
+
+    // Moves a fully copied staging file over the destination.
+    fn commit(job: &Arc<Job>, destination_path: &Path) -> Result<()> {
+        if !job.options.atomic {
+            return Ok(());
+        }
+
+        fs::rename(CopyService::staging_path(job, destination_path), destination_path)?;
+
+        // make the rename itself durable
+        let parent = match destination_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+        Ok(())
+    }
+
+    // Removes the staging file of an atomic job, the file has to be copied from the start again.
+    fn discard(storage: &Arc<RwLock<StorageService>>, job: Arc<Job>, destination_path: &Path) {
+        if !job.options.atomic {
+            return;
+        }
+
+        if let Err(err) = fs::remove_file(CopyService::staging_path(&job, destination_path)) {
+            if err.kind() != ErrorKind::NotFound {
+                eprintln!("Error removing staging file of job {}: {}", job.id, err);
+            }
+        }
+
+        StorageService::rewind_job_file(job.clone());
+        storage.read().unwrap().checkpoint_job(job);
+    }
+
+    // Drops the staging file a canceled atomic job left behind, that of the single file or of the file a tree was
+    // in the middle of. Only the destination is looked at, callers must not be impersonating the owner already.
+    fn clean_up(storage: &Arc<RwLock<StorageService>>, job: Arc<Job>) {
+        let _impersonation = match job.owner.map(Impersonation::start).transpose() {
+            Ok(impersonation) => impersonation.flatten(),
+            Err(err) => return eprintln!("Error cleaning up after job {}: {}", job.id, err),
+        };
+
+        let destination_path = job.destination_path();
+        if !destination_path.is_dir() {
+            return CopyService::discard(storage, job, &destination_path);
+        }
+        if !job.options.atomic {
+            return;
+        }
+
+        let suffix = format!(".{}.tmp", job.id);
+        let entries = tree::walk(&destination_path).unwrap_or_default();
+        let staging_files = entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::File && entry.path.to_string_lossy().ends_with(&suffix));
+        for entry in staging_files {
+            if let Err(err) = fs::remove_file(destination_path.join(&entry.path)) {
+                eprintln!("Error removing staging file of job {}: {}", job.id, err);
+            }
+        }
+
+        StorageService::rewind_job_file(job.clone());
+        storage.read().unwrap().checkpoint_job(job);
+    }
+
+    // Copies from the job's durable offset to the end of the source with the job's copy strategy, falling back
+    // to the next strategy for as long as the current one is unsupported and has not copied anything yet.
+    // Finishes once the whole source has been copied and synced to disk.
+    fn copy_file(
+        config: &Arc<Config>,
+        storage: &Arc<RwLock<StorageService>>,
+        job: Arc<Job>,
+        mut source: BufReader<File>,
+        mut destination: BufWriter<File>,
+        hasher: &mut Option<Hasher>,
+    ) -> Result<Outcome> {
+        let start = *job.offset.read().unwrap();
+        let mut position = start;
+
+        let mut method = match job.options.strategy {
+            CopyStrategy::Auto => CopyStrategy::Reflink,
+            strategy => strategy,
+        };
+        // a reflink always replaces the whole destination
+        if method == CopyStrategy::Reflink && start > 0 {
+            method = method.fallback();
+        }
+
+        let mut buffer: Vec<u8> = vec![0; config.buffer_size];
+        let mut copied_any = false;
+        let mut data_end = position; // end of the data region of the source being copied
+        loop {
+            if let Some(outcome) = CopyService::handle_interruptions(storage, job.clone(), &mut destination, position)? {
+                return Ok(outcome);
+            }
+
+            // holes of sparse sources are skipped, the destination stays sparse since nothing is written there
+            if method != CopyStrategy::Reflink && position >= data_end {
+                let hash_hole = method == CopyStrategy::Buffered;
+                match kernel::next_data(source.get_ref(), position)? {
+                    Some((data_start, hole_start)) => {
+                        CopyService::skip_hole(job.clone(), hasher, hash_hole, position, data_start);
+                        position = data_start;
+                        data_end = hole_start;
+                    }
+                    None => {
+                        let end = source.get_ref().metadata()?.len().max(position);
+                        CopyService::skip_hole(job.clone(), hasher, hash_hole, position, end);
+                        position = end;
+                        break;
+                    }
+                }
+
+                // looking for data moved the source's file offset
+                source.seek(SeekFrom::Start(position))?;
+                destination.seek(SeekFrom::Start(position))?;
+            }
+            let len = (data_end - position).min(config.buffer_size as u64) as usize;
+
+            let copied = match method {
+                CopyStrategy::Reflink => kernel::reflink(source.get_ref(), destination.get_ref()),
+                CopyStrategy::CopyFileRange => kernel::copy_file_range(source.get_ref(), destination.get_ref(), position, len),
+                CopyStrategy::Sendfile => kernel::sendfile(source.get_ref(), destination.get_ref(), position, len),
+                _ => CopyService::copy_buffered(&mut source, &mut destination, &mut buffer[..len], hasher),
+            };
+
+            let bytes_copied = match copied {
+                Err(err) if !copied_any && method != CopyStrategy::Buffered && kernel::is_unsupported(&err) => {
+                    method = method.fallback();
+                    // nothing was copied yet, so everything skipped since the start is holes that went
+                    // unhashed, the buffered copy only hashes what it reads from here on
+                    if method == CopyStrategy::Buffered {
+                        if let Some(hasher) = hasher.as_mut() {
+                            hasher.update_zeros(position - start);
+                        }
+                    }
+                    continue;
+                }
+                result => result?,
+            };
+            if bytes_copied == 0 {
                 break;
             }
 
-            match destination.write_all(&buffer[..bytes_read]) {
-                Ok(_) => StorageService::increment_job_writes(job.clone()),
-                Err(_) => {
-                    StorageService::increment_job_writes(job.clone());
-                    return Ok(job.clone());
-                },
+            if !copied_any {
+                StorageService::update_job_method(job.clone(), method);
+                copied_any = true;
+            }
+
+            position += bytes_copied as u64;
+            StorageService::increment_job_writes(job.clone(), bytes_copied as u64);
+
+            if job.writes.read().unwrap().is_multiple_of(config.checkpoint_interval) {
+                CopyService::checkpoint(storage, job.clone(), &mut destination, position)?;
             }
 
             if config.testing {
                 thread::sleep(Duration::from_secs_f32(config.delay));
             }
 
-            if *job.status.read().unwrap() == JobStatus::Canceled {
-                destination.flush()?;
-                return Ok(job.clone());
-            } 
-
-            if *job.status.read().unwrap() == JobStatus::Suspended {
-                destination.flush()?;
+            if method == CopyStrategy::Reflink {
+                break;
+            }
+        }
 
-                while *job.status.read().unwrap() != JobStatus::Resumed {
-                    thread::yield_now();
-                    thread::sleep(Duration::from_secs_f32(config.delay));
-                }                   
+        // the kernel copies never pass the data through userspace, hash it from the source instead
+        if method != CopyStrategy::Buffered {
+            if let Some(hasher) = hasher.as_mut() {
+                let mut source_file = source.get_ref();
+                source_file.seek(SeekFrom::Start(start))?;
+                hasher.update_from_reader(source_file)?;
             }
         }
 
+        // a trailing hole is never written, extend the destination over it
         destination.flush()?;
-        Ok(job.clone())
+        destination.get_ref().set_len(position)?;
+        destination.get_ref().sync_data()?;
+        Ok(Outcome::Finished)
     }
 
-    fn source_reader(config: &Arc<Config>, job: Arc<Job>) -> Result<BufReader<File>> {
-        match *job.writes.read().unwrap() {
-            writes if writes > 0 => {
-                let offset = config.buffer_size as u64 * writes;
-                let mut source = OpenOptions::new().read(true).open(&job.source)?;
+    // Accounts for the hole [from, to) of the source, which reads as zeros.
+    fn skip_hole(job: Arc<Job>, hasher: &mut Option<Hasher>, hash_hole: bool, from: u64, to: u64) {
+        if to <= from {
+            return;
+        }
 
-                source.seek(SeekFrom::Start(offset))?;
-                Ok(BufReader::new(source))
+        if hash_hole {
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update_zeros(to - from);
             }
-            _ => Ok(BufReader::new(File::open(&job.source)?)),
         }
+        StorageService::increment_job_bytes(job, to - from);
     }
 
-    fn destination_writer(job: &Arc<Job>) -> Result<BufWriter<File>> {
-        let destination = OpenOptions::new()
-            .append(true)
+    fn copy_buffered(
+        source: &mut BufReader<File>,
+        destination: &mut BufWriter<File>,
+        buffer: &mut [u8],
+        hasher: &mut Option<Hasher>,
+    ) -> io::Result<usize> {
+        let bytes_read = source.read(buffer)?;
+        destination.write_all(&buffer[..bytes_read])?;
+
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buffer[..bytes_read]);
+        }
+        Ok(bytes_read)
+    }
+
+    // Checkpoints the job when it gets canceled or suspended. A suspended job is parked, its worker goes back
+    // to the pool and resuming the job dispatches it again. Returns how the copy ended if it was interrupted.
+    fn handle_interruptions(
+        storage: &Arc<RwLock<StorageService>>,
+        job: Arc<Job>,
+        destination: &mut BufWriter<File>,
+        position: u64,
+    ) -> Result<Option<Outcome>> {
+        loop {
+            let status = job.status.read().unwrap().clone();
+            if status != JobStatus::Canceled && status != JobStatus::Suspended {
+                return Ok(None);
+            }
+
+            CopyService::checkpoint(storage, job.clone(), destination, position)?;
+
+            if status == JobStatus::Canceled {
+                return Ok(Some(Outcome::Canceled));
+            }
+
+            // the job may have been resumed or canceled while checkpointing
+            if StorageService::park_job(job.clone()) {
+                return Ok(Some(Outcome::Suspended));
+            }
+        }
+    }
+
+    // Makes everything written so far durable and records `position` as the offset to resume from.
+    fn checkpoint(storage: &Arc<RwLock<StorageService>>, job: Arc<Job>, destination: &mut BufWriter<File>, position: u64) -> Result<()> {
+        destination.flush()?;
+        destination.get_ref().sync_data()?;
+
+        StorageService::update_job_offset(job.clone(), position);
+        storage.read().unwrap().checkpoint_job(job);
+        Ok(())
+    }
+
+    fn source_reader(job: Arc<Job>, path: &Path) -> Result<BufReader<File>> {
+        let mut source = File::open(path)?;
+        source.seek(SeekFrom::Start(*job.offset.read().unwrap()))?;
+        Ok(BufReader::new(source))
+    }
+
+    // Anything past the durable offset may be a partial write from an interrupted run, drop it before appending.
+    fn destination_writer(job: Arc<Job>, path: &Path) -> Result<BufWriter<File>> {
+        let offset = *job.offset.read().unwrap();
+        let mut destination = OpenOptions::new()
+            .write(true)
             .create(true)
-            .open(&job.destination)?;
+            .truncate(false)
+            .open(path)?;
 
+        destination.set_len(offset)?;
+        destination.seek(SeekFrom::Start(offset))?;
         Ok(BufWriter::new(destination))
     }
-}
\ No newline at end of file
+}
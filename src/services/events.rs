@@ -0,0 +1,30 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::models::job::{Job, JobStatus};
+
+// A job entered `status`.
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    pub job: Arc<Job>,
+    pub status: JobStatus,
+}
+
+// Hands every status change to whoever subscribed to them. Subscribers that went away are dropped on the next change.
+#[derive(Default)]
+pub struct Events {
+    subscribers: Mutex<Vec<Sender<StatusChange>>>,
+}
+
+impl Events {
+    pub fn subscribe(&self) -> Receiver<StatusChange> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, job: &Arc<Job>, status: &JobStatus) {
+        let change = StatusChange { job: job.clone(), status: status.clone() };
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(change.clone()).is_ok());
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::models::job::{Job, JobOptions, JobStatus, Owner, Priority, Progress, Resolution, Timestamps};
+
+// One line of the journal. Replaying every event in order rebuilds the state of all jobs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum JournalEvent {
+    Created {
+        id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>, // missing in journals written before jobs were numbered
+        source: String,
+        destination: String,
+        #[serde(default)]
+        options: JobOptions,
+        #[serde(default)]
+        priority: Priority,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<Owner>,
+        #[serde(default)]
+        timestamps: Timestamps,
+    },
+    Status {
+        id: Uuid,
+        status: JobStatus,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        at: Option<u64>, // when the job started or finished, for those statuses
+    },
+    Checkpoint {
+        id: Uuid,
+        writes: u64,
+        files: u64,
+        bytes: u64,
+        #[serde(default)]
+        stored: u64,
+        offset: u64,
+    },
+    Digest {
+        id: Uuid,
+        digest: String,
+    },
+    Resolved {
+        id: Uuid,
+        resolution: Resolution,
+    },
+    Priority {
+        id: Uuid,
+        priority: Priority,
+    },
+    Total {
+        id: Uuid,
+        total: u64,
+    },
+    Attempt {
+        id: Uuid,
+        attempts: u32,
+    },
+    Removed {
+        id: Uuid,
+    },
+}
+
+impl JournalEvent {
+    pub fn created(job: &Arc<Job>) -> Self {
+        JournalEvent::Created {
+            id: job.id,
+            seq: Some(job.seq),
+            source: job.source.clone(),
+            destination: job.destination.clone(),
+            options: job.options.clone(),
+            priority: *job.priority.read().unwrap(),
+            owner: job.owner,
+            timestamps: *job.timestamps.read().unwrap(),
+        }
+    }
+
+    // Takes the status rather than reading it, so that it can be recorded while the status lock is held.
+    pub fn status(job: &Arc<Job>, status: &JobStatus) -> Self {
+        let status = status.clone();
+        let timestamps = job.timestamps.read().unwrap();
+        let at = match &status {
+            JobStatus::Running => timestamps.started,
+            status if status.is_finished() => timestamps.finished,
+            _ => None,
+        };
+
+        JournalEvent::Status { id: job.id, status, at }
+    }
+
+    pub fn total(job: &Arc<Job>) -> Option<Self> {
+        job.total.read().unwrap().map(|total| JournalEvent::Total { id: job.id, total })
+    }
+
+    pub fn attempt(job: &Arc<Job>) -> Self {
+        JournalEvent::Attempt { id: job.id, attempts: *job.attempts.read().unwrap() }
+    }
+
+    pub fn priority(job: &Arc<Job>) -> Self {
+        JournalEvent::Priority {
+            id: job.id,
+            priority: *job.priority.read().unwrap(),
+        }
+    }
+
+    // The progress last checkpointed, the job's counters may already be ahead of what is durable.
+    pub fn checkpoint(job: &Arc<Job>) -> Self {
+        let Progress { writes, files, bytes, stored, offset } = *job.checkpointed.read().unwrap();
+        JournalEvent::Checkpoint { id: job.id, writes, files, bytes, stored, offset }
+    }
+
+    pub fn digest(job: &Arc<Job>) -> Option<Self> {
+        job.digest.read().unwrap().clone().map(|digest| JournalEvent::Digest { id: job.id, digest })
+    }
+
+    pub fn resolved(job: &Arc<Job>) -> Option<Self> {
+        job.resolution.read().unwrap().clone().map(|resolution| JournalEvent::Resolved { id: job.id, resolution })
+    }
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    // Replays the journal at `path` (if any), compacts it to a single snapshot of the
+    // replayed jobs and opens it for appending.
+    pub fn load(path: &Path) -> Result<(Self, Vec<Arc<Job>>)> {
+        let jobs: Vec<Arc<Job>> = Journal::replay(path)?.into_iter().map(Arc::new).collect();
+        let file = Journal::compact(path, &jobs)?;
+
+        Ok((Journal { path: path.to_path_buf(), file: Mutex::new(file) }, jobs))
+    }
+
+    // Compacts the journal to a snapshot of `jobs` while the daemon runs. Events recorded meanwhile wait for the
+    // snapshot and are appended to it. Status changes are recorded under the job's status lock, callers have to
+    // keep jobs from changing status, or the snapshot would wait for them while they wait for the journal.
+    pub fn rewrite(&self, jobs: &[Arc<Job>]) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        // the journal is only swapped once nothing can fail anymore, it is left as it was otherwise
+        *file = Journal::compact(&self.path, jobs)?;
+        Ok(())
+    }
+
+    pub fn record(&self, event: &JournalEvent) -> Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn replay(path: &Path) -> Result<Vec<Job>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut jobs: Vec<Job> = Vec::new();
+        let mut index: HashMap<Uuid, usize> = HashMap::new();
+        let mut removed: HashSet<Uuid> = HashSet::new();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // a crash in the middle of a write can leave a torn last line behind
+            let event: JournalEvent = match serde_json::from_str(&line) {
+                Ok(event) => event,
+                Err(err) => {
+                    eprintln!("Skipping malformed journal entry: {}", err);
+                    continue;
+                }
+            };
+
+            match event {
+                JournalEvent::Created { id, seq, source, destination, options, priority, owner, timestamps } => {
+                    index.insert(id, jobs.len());
+                    // jobs are journaled in the order they were submitted
+                    let seq = seq.unwrap_or_else(|| jobs.last().map_or(0, |last: &Job| last.seq + 1));
+                    jobs.push(Job {
+                        id,
+                        seq,
+                        owner,
+                        priority: Arc::new(RwLock::new(priority)),
+                        timestamps: Arc::new(RwLock::new(timestamps)),
+                        ..Job::new(source, destination, options)
+                    });
+                }
+                JournalEvent::Status { id, status, at } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        let mut timestamps = job.timestamps.write().unwrap();
+                        if status == JobStatus::Running {
+                            timestamps.started = timestamps.started.or(at);
+                        }
+                        timestamps.finished = if status.is_finished() { at } else { None };
+                        *job.status.write().unwrap() = status;
+                    }
+                }
+                JournalEvent::Checkpoint { id, writes, files, bytes, stored, offset } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.writes.write().unwrap() = writes;
+                        *job.files.write().unwrap() = files;
+                        *job.bytes.write().unwrap() = bytes;
+                        *job.stored.write().unwrap() = stored;
+                        *job.offset.write().unwrap() = offset;
+                        *job.checkpointed.write().unwrap() = Progress { writes, files, bytes, stored, offset };
+                    }
+                }
+                JournalEvent::Digest { id, digest } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.digest.write().unwrap() = Some(digest);
+                    }
+                }
+                JournalEvent::Resolved { id, resolution } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.resolution.write().unwrap() = Some(resolution);
+                    }
+                }
+                JournalEvent::Priority { id, priority } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.priority.write().unwrap() = priority;
+                    }
+                }
+                JournalEvent::Total { id, total } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.total.write().unwrap() = Some(total);
+                    }
+                }
+                JournalEvent::Attempt { id, attempts } => {
+                    if let Some(job) = index.get(&id).map(|&i| &jobs[i]) {
+                        *job.attempts.write().unwrap() = attempts;
+                    }
+                }
+                JournalEvent::Removed { id } => {
+                    removed.insert(id);
+                }
+            }
+        }
+
+        // dropped only now, positions in `index` have to stay valid while replaying
+        jobs.retain(|job| !removed.contains(&job.id));
+        Ok(jobs)
+    }
+
+    // Replaces the journal at `path` with a snapshot of `jobs`. Returns the snapshot opened for appending,
+    // it is the journal from then on.
+    fn compact(path: &Path, jobs: &[Arc<Job>]) -> Result<File> {
+        let compacted_path = path.with_extension("compact");
+        // left behind by a compaction that failed
+        if compacted_path.exists() {
+            fs::remove_file(&compacted_path)?;
+        }
+        let mut compacted = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&compacted_path)?;
+
+        for job in jobs {
+            let events = [JournalEvent::created(job), JournalEvent::status(job, &job.status.read().unwrap()), JournalEvent::checkpoint(job), JournalEvent::attempt(job)]
+                .into_iter()
+                .chain(JournalEvent::digest(job))
+                .chain(JournalEvent::resolved(job))
+                .chain(JournalEvent::total(job));
+
+            for event in events {
+                writeln!(compacted, "{}", serde_json::to_string(&event)?)?;
+            }
+        }
+
+        compacted.sync_all()?;
+        fs::rename(compacted_path, path)?;
+        Ok(compacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn journal_path() -> PathBuf {
+        std::env::temp_dir().join(format!("copy-service-journal-{}.jsonl", Uuid::new_v4()))
+    }
+
+    fn write_journal(path: &Path, lines: &[String]) {
+        fs::write(path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+    }
+
+    fn line(event: JournalEvent) -> String {
+        serde_json::to_string(&event).unwrap()
+    }
+
+    fn new_job(source: &str) -> Arc<Job> {
+        Arc::new(Job::new(source.to_string(), format!("{}.copy", source), JobOptions::default()))
+    }
+
+    #[test]
+    fn replay_rebuilds_the_last_state_of_each_job() {
+        let path = journal_path();
+        let (first, second) = (new_job("/a"), new_job("/b"));
+        write_journal(&path, &[
+            line(JournalEvent::created(&first)),
+            line(JournalEvent::created(&second)),
+            line(JournalEvent::Status { id: first.id, status: JobStatus::Running, at: Some(10) }),
+            line(JournalEvent::Checkpoint { id: first.id, writes: 1, files: 0, bytes: 128, stored: 128, offset: 128 }),
+            line(JournalEvent::Checkpoint { id: first.id, writes: 2, files: 0, bytes: 256, stored: 256, offset: 256 }),
+            line(JournalEvent::Attempt { id: first.id, attempts: 2 }),
+            line(JournalEvent::Status { id: first.id, status: JobStatus::Completed, at: Some(20) }),
+            line(JournalEvent::Status { id: second.id, status: JobStatus::Suspended, at: None }),
+        ]);
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+        assert_eq!(ids, vec![first.id, second.id]);
+        assert_eq!(jobs[0].source, "/a");
+        assert_eq!(*jobs[0].status.read().unwrap(), JobStatus::Completed);
+        assert_eq!(*jobs[0].offset.read().unwrap(), 256);
+        assert_eq!(*jobs[0].attempts.read().unwrap(), 2);
+        assert_eq!(jobs[0].timestamps.read().unwrap().started, Some(10));
+        assert_eq!(jobs[0].timestamps.read().unwrap().finished, Some(20));
+        assert_eq!(*jobs[1].status.read().unwrap(), JobStatus::Suspended);
+    }
+
+    #[test]
+    fn replay_clears_the_finish_time_of_a_requeued_job() {
+        let path = journal_path();
+        let job = new_job("/a");
+        write_journal(&path, &[
+            line(JournalEvent::created(&job)),
+            line(JournalEvent::Status { id: job.id, status: JobStatus::Failed("disk full".to_string()), at: Some(20) }),
+            line(JournalEvent::Status { id: job.id, status: JobStatus::Queued, at: None }),
+        ]);
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(*jobs[0].status.read().unwrap(), JobStatus::Queued);
+        assert_eq!(jobs[0].timestamps.read().unwrap().finished, None);
+    }
+
+    #[test]
+    fn replay_drops_removed_jobs_and_keeps_the_order_of_the_others() {
+        let path = journal_path();
+        let (first, second, third) = (new_job("/a"), new_job("/b"), new_job("/c"));
+        write_journal(&path, &[
+            line(JournalEvent::created(&first)),
+            line(JournalEvent::created(&second)),
+            line(JournalEvent::created(&third)),
+            line(JournalEvent::Status { id: second.id, status: JobStatus::Completed, at: Some(20) }),
+            line(JournalEvent::Removed { id: second.id }),
+            // events of jobs after a removed one still find their job
+            line(JournalEvent::Priority { id: third.id, priority: Priority::Urgent }),
+        ]);
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+        assert_eq!(ids, vec![first.id, third.id]);
+        assert_eq!(*jobs[1].priority.read().unwrap(), Priority::Urgent);
+    }
+
+    #[test]
+    fn replay_numbers_jobs_journaled_without_a_submission_number_in_journal_order() {
+        let path = journal_path();
+        let (first, second) = (new_job("/a"), new_job("/b"));
+        let unnumbered = |job: &Arc<Job>| line(JournalEvent::created(job)).replace("\"seq\":0,", "");
+        write_journal(&path, &[unnumbered(&first), unnumbered(&second)]);
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(jobs.iter().map(|job| job.seq).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn replay_skips_malformed_lines_and_events_of_unknown_jobs() {
+        let path = journal_path();
+        let job = new_job("/a");
+        write_journal(&path, &[
+            line(JournalEvent::Status { id: Uuid::new_v4(), status: JobStatus::Running, at: None }),
+            line(JournalEvent::created(&job)),
+            "{\"event\":\"checkpoint\",\"id\":".to_string(),
+        ]);
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(*jobs[0].status.read().unwrap(), JobStatus::Created);
+        assert_eq!(*jobs[0].offset.read().unwrap(), 0);
+    }
+
+    #[test]
+    fn load_compacts_the_journal_to_what_replay_rebuilds() {
+        let path = journal_path();
+        let job = new_job("/a");
+        write_journal(&path, &[
+            line(JournalEvent::created(&job)),
+            line(JournalEvent::Checkpoint { id: job.id, writes: 1, files: 0, bytes: 128, stored: 128, offset: 128 }),
+            line(JournalEvent::Checkpoint { id: job.id, writes: 2, files: 0, bytes: 256, stored: 256, offset: 256 }),
+        ]);
+
+        let (journal, _) = Journal::load(&path).unwrap();
+        journal.record(&JournalEvent::Status { id: job.id, status: JobStatus::Canceled, at: Some(20) }).unwrap();
+
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(lines, 5);
+        assert_eq!(*jobs[0].offset.read().unwrap(), 256);
+        assert_eq!(*jobs[0].status.read().unwrap(), JobStatus::Canceled);
+    }
+
+    #[test]
+    fn rewrite_compacts_to_what_replay_rebuilds() {
+        let path = journal_path();
+        let (journal, _) = Journal::load(&path).unwrap();
+        let (kept, removed) = (new_job("/a"), new_job("/b"));
+        for job in [&kept, &removed] {
+            journal.record(&JournalEvent::created(job)).unwrap();
+        }
+        *kept.checkpointed.write().unwrap() = Progress { writes: 3, files: 0, bytes: 384, stored: 384, offset: 384 };
+        // counters ahead of the last checkpoint are not durable yet
+        *kept.offset.write().unwrap() = 512;
+        journal.record(&JournalEvent::checkpoint(&kept)).unwrap();
+        journal.record(&JournalEvent::Removed { id: removed.id }).unwrap();
+
+        journal.rewrite(std::slice::from_ref(&kept)).unwrap();
+        journal.record(&JournalEvent::Priority { id: kept.id, priority: Priority::Low }).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!contents.contains(&removed.id.to_string()));
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(*jobs[0].offset.read().unwrap(), 384);
+        assert_eq!(*jobs[0].priority.read().unwrap(), Priority::Low);
+    }
+
+    #[test]
+    fn failed_rewrite_keeps_the_journal_in_use() {
+        let path = journal_path();
+        let (journal, _) = Journal::load(&path).unwrap();
+        let job = new_job("/a");
+        journal.record(&JournalEvent::created(&job)).unwrap();
+
+        // the snapshot cannot be written where a directory is in the way
+        fs::create_dir(path.with_extension("compact")).unwrap();
+        assert!(journal.rewrite(std::slice::from_ref(&job)).is_err());
+        fs::remove_dir(path.with_extension("compact")).unwrap();
+        journal.record(&JournalEvent::Priority { id: job.id, priority: Priority::Low }).unwrap();
+
+        let jobs = Journal::replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(*jobs[0].priority.read().unwrap(), Priority::Low);
+    }
+}
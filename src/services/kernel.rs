@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::{Error, Result};
+use std::os::unix::io::AsRawFd;
+
+// Errors meaning the kernel (or the filesystems involved) cannot copy this way, as opposed to a failed copy.
+pub fn is_unsupported(err: &Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EXDEV | libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP | libc::ENOTTY | libc::EBADF)
+    )
+}
+
+// Shares the extents of `source` with `destination` on copy-on-write filesystems (btrfs, XFS, ...).
+// The whole destination is replaced by the source. Returns the nr. of bytes cloned.
+pub fn reflink(source: &File, destination: &File) -> Result<usize> {
+    match unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(source.metadata()?.len() as usize),
+    }
+}
+
+// Copies up to `len` bytes at `offset` of `source` to the same offset of `destination` without going
+// through userspace. Returns the nr. of bytes copied, 0 at the end of the source.
+pub fn copy_file_range(source: &File, destination: &File, offset: u64, len: usize) -> Result<usize> {
+    let mut source_offset = offset as libc::loff_t;
+    let mut destination_offset = offset as libc::loff_t;
+
+    let copied = unsafe {
+        libc::copy_file_range(
+            source.as_raw_fd(),
+            &mut source_offset,
+            destination.as_raw_fd(),
+            &mut destination_offset,
+            len,
+            0,
+        )
+    };
+
+    match copied {
+        -1 => Err(Error::last_os_error()),
+        copied => Ok(copied as usize),
+    }
+}
+
+// Copies up to `len` bytes at `offset` of `source` to the current position of `destination`.
+// Returns the nr. of bytes copied, 0 at the end of the source.
+pub fn sendfile(source: &File, destination: &File, offset: u64, len: usize) -> Result<usize> {
+    let mut source_offset = offset as libc::off_t;
+
+    match unsafe { libc::sendfile(destination.as_raw_fd(), source.as_raw_fd(), &mut source_offset, len) } {
+        -1 => Err(Error::last_os_error()),
+        copied => Ok(copied as usize),
+    }
+}
+
+// First data region of `file` at or after `offset`, as (start of the data, start of the following hole).
+// None if there is nothing but a hole from `offset` up to the end of the file. Filesystems that do not
+// support SEEK_DATA report everything from `offset` on as data.
+pub fn next_data(file: &File, offset: u64) -> Result<Option<(u64, u64)>> {
+    let data_start = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, libc::SEEK_DATA) };
+    if data_start == -1 {
+        let err = Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENXIO) => Ok(None),
+            Some(libc::EINVAL) => Ok(Some((offset, u64::MAX))),
+            _ => Err(err),
+        };
+    }
+
+    match unsafe { libc::lseek(file.as_raw_fd(), data_start, libc::SEEK_HOLE) } {
+        -1 => Err(Error::last_os_error()),
+        hole_start => Ok(Some((data_start as u64, hole_start as u64))),
+    }
+}
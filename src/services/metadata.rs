@@ -0,0 +1,63 @@
+use std::fs;
+use std::os::unix::fs::{chown, MetadataExt};
+use std::path::Path;
+use anyhow::Result;
+use filetime::FileTime;
+
+use crate::models::job::Preserve;
+use crate::services::{access, tree};
+
+// POSIX ACLs are stored as extended attributes in this namespace.
+const ACL_PREFIX: &str = "system.posix_acl_";
+
+// Applies the selected metadata of `source` to `destination`.
+pub fn apply(preserve: &[Preserve], source: &Path, destination: &Path) -> Result<()> {
+    let source_meta = fs::metadata(source)?;
+
+    // changing the owner clears setuid/setgid bits, so it has to happen before the mode is set
+    if preserve.contains(&Preserve::Ownership) && access::is_privileged() {
+        chown(destination, Some(source_meta.uid()), Some(source_meta.gid()))?;
+    }
+    if preserve.contains(&Preserve::Mode) {
+        fs::set_permissions(destination, source_meta.permissions())?;
+    }
+
+    let copy_xattrs = preserve.contains(&Preserve::Xattrs);
+    let copy_acls = preserve.contains(&Preserve::Acls);
+    if copy_xattrs || copy_acls {
+        for name in xattr::list(source)? {
+            let is_acl = name.to_string_lossy().starts_with(ACL_PREFIX);
+            if (is_acl && !copy_acls) || (!is_acl && !copy_xattrs) {
+                continue;
+            }
+
+            if let Some(value) = xattr::get(source, &name)? {
+                xattr::set(destination, &name, &value)?;
+            }
+        }
+    }
+
+    // last, everything above may touch the destination's timestamps
+    if preserve.contains(&Preserve::Timestamps) {
+        filetime::set_file_times(
+            destination,
+            FileTime::from_last_access_time(&source_meta),
+            FileTime::from_last_modification_time(&source_meta),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Applies the selected metadata to every file and directory of a copied tree. Directories are handled
+// after their contents, so that writing the contents does not change their timestamps afterwards.
+pub fn apply_tree(preserve: &[Preserve], source_root: &Path, destination_root: &Path) -> Result<()> {
+    for entry in tree::walk(source_root)?.iter().rev() {
+        let destination = destination_root.join(&entry.path);
+        if destination.exists() {
+            apply(preserve, &source_root.join(&entry.path), &destination)?;
+        }
+    }
+
+    apply(preserve, source_root, destination_root)
+}
@@ -1,4 +1,13 @@
 pub mod validate;
 pub mod storage;
 pub mod copy;
+pub mod tree;
+pub mod journal;
+pub mod checksum;
+pub mod metadata;
+pub mod kernel;
 
+pub mod scheduler;
+pub mod access;
+pub mod events;
+pub mod retention;
\ No newline at end of file
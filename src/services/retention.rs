@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+
+use crate::models::config::Retention;
+use crate::models::job::{Job, Timestamps};
+use crate::services::storage::StorageService;
+
+// Removes finished jobs the retention policy no longer keeps, so that the job history stays bounded.
+pub struct RetentionService {
+    retention: Retention,
+    storage: Arc<RwLock<StorageService>>,
+}
+
+impl RetentionService {
+    pub fn new(retention: Retention, storage: Arc<RwLock<StorageService>>) -> Self {
+        RetentionService { retention, storage }
+    }
+
+    // Sweeps right away, then every `interval` seconds, forever.
+    pub fn execute(&self) {
+        loop {
+            match self.sweep() {
+                Ok(0) => {}
+                Ok(removed) => eprintln!("Removed {} finished job(s) past retention", removed),
+                Err(err) => eprintln!("Error applying retention policy: {}", err),
+            }
+            thread::sleep(Duration::from_secs(self.retention.interval));
+        }
+    }
+
+    fn sweep(&self) -> Result<usize> {
+        let now = Timestamps::now();
+        let jobs = self.storage.read().unwrap().jobs().read().unwrap().clone();
+
+        // finished jobs the count limit applies to, most recently finished first
+        let mut counted: Vec<(u64, &Arc<Job>)> = Vec::new();
+        let mut expired = HashSet::new();
+        for job in &jobs {
+            let status = job.status.read().unwrap().clone();
+            if !status.is_finished() {
+                continue;
+            }
+
+            // jobs journaled before timestamps were recorded count as the oldest ones
+            let timestamps = *job.timestamps.read().unwrap();
+            let finished = timestamps.finished.or(timestamps.created).unwrap_or(0);
+            let age = now.saturating_sub(finished) / 1000;
+
+            match self.retention.failed_max_age {
+                Some(max_age) if status.is_failure() => {
+                    if age > max_age {
+                        expired.insert(job.id);
+                    }
+                }
+                _ if self.retention.max_age.is_some_and(|max_age| age > max_age) => {
+                    expired.insert(job.id);
+                }
+                _ => counted.push((finished, job)),
+            }
+        }
+
+        if let Some(max_count) = self.retention.max_count {
+            counted.sort_by_key(|(finished, _)| std::cmp::Reverse(*finished));
+            expired.extend(counted.iter().skip(max_count).map(|(_, job)| job.id));
+        }
+
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        Ok(self.storage.write().unwrap().remove_jobs(|job| expired.contains(&job.id))?.len())
+    }
+}
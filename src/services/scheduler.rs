@@ -0,0 +1,121 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::job::{Job, Priority};
+
+// Jobs waiting for a worker. The job with the highest priority goes first, jobs of the same priority
+// in the order they were queued. Priorities are read when a job is picked, so changing one reorders the queue.
+#[derive(Default)]
+pub struct Scheduler {
+    queue: Vec<Arc<Job>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn push(&mut self, job: Arc<Job>) {
+        if !self.contains(job.id) {
+            self.queue.push(job);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Arc<Job>> {
+        (0..self.queue.len())
+            .max_by_key(|&index| (*self.queue[index].priority.read().unwrap(), Reverse(index)))
+            .map(|index| self.queue.remove(index))
+    }
+
+    pub fn contains(&self, job_id: Uuid) -> bool {
+        self.queue.iter().any(|job| job.id == job_id)
+    }
+
+    // Puts the job at the head of the queue. Returns the priority it needs to be picked next,
+    // the highest one among the other queued jobs, or None if the job is not queued.
+    pub fn move_to_front(&mut self, job_id: Uuid) -> Option<Priority> {
+        let index = self.queue.iter().position(|job| job.id == job_id)?;
+        let job = self.queue.remove(index);
+
+        let highest = self.queue.iter().map(|job| *job.priority.read().unwrap()).max().unwrap_or_default();
+        self.queue.insert(0, job);
+        Some(highest)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job::JobOptions;
+
+    fn queued(scheduler: &mut Scheduler, priority: Priority) -> Uuid {
+        let job = Job::new("/source".to_string(), "/destination".to_string(), JobOptions::default());
+        *job.priority.write().unwrap() = priority;
+        let id = job.id;
+        scheduler.push(Arc::new(job));
+        id
+    }
+
+    fn drain(scheduler: &mut Scheduler) -> Vec<Uuid> {
+        std::iter::from_fn(|| scheduler.pop()).map(|job| job.id).collect()
+    }
+
+    #[test]
+    fn pop_picks_by_priority_then_in_queue_order() {
+        let mut scheduler = Scheduler::new();
+        let low = queued(&mut scheduler, Priority::Low);
+        let first = queued(&mut scheduler, Priority::Normal);
+        let high = queued(&mut scheduler, Priority::High);
+        let second = queued(&mut scheduler, Priority::Normal);
+
+        assert_eq!(drain(&mut scheduler), vec![high, first, second, low]);
+        assert!(scheduler.pop().is_none());
+    }
+
+    #[test]
+    fn pop_reads_priorities_changed_while_queued() {
+        let mut scheduler = Scheduler::new();
+        let first = queued(&mut scheduler, Priority::Normal);
+        let second = queued(&mut scheduler, Priority::Normal);
+        *scheduler.queue[1].priority.write().unwrap() = Priority::Urgent;
+
+        assert_eq!(drain(&mut scheduler), vec![second, first]);
+    }
+
+    #[test]
+    fn push_ignores_a_job_already_queued() {
+        let mut scheduler = Scheduler::new();
+        queued(&mut scheduler, Priority::Normal);
+        let job = scheduler.queue[0].clone();
+        scheduler.push(job);
+
+        assert_eq!(drain(&mut scheduler).len(), 1);
+    }
+
+    #[test]
+    fn move_to_front_returns_the_highest_other_priority() {
+        let mut scheduler = Scheduler::new();
+        let first = queued(&mut scheduler, Priority::Normal);
+        let high = queued(&mut scheduler, Priority::High);
+        let low = queued(&mut scheduler, Priority::Low);
+
+        assert_eq!(scheduler.move_to_front(low), Some(Priority::High));
+        assert_eq!(scheduler.queue[0].id, low);
+
+        // raised to that priority, as the handler does, it is picked next as the earliest of the highest
+        *scheduler.queue[0].priority.write().unwrap() = Priority::High;
+        assert_eq!(drain(&mut scheduler), vec![low, high, first]);
+    }
+
+    #[test]
+    fn move_to_front_of_a_lone_or_unknown_job() {
+        let mut scheduler = Scheduler::new();
+        let only = queued(&mut scheduler, Priority::High);
+
+        assert_eq!(scheduler.move_to_front(only), Some(Priority::default()));
+        assert_eq!(scheduler.move_to_front(Uuid::new_v4()), None);
+        assert_eq!(drain(&mut scheduler), vec![only]);
+    }
+}
@@ -1,51 +1,158 @@
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, RwLock};
 use std::str::FromStr;
+use std::path::Path;
+use anyhow::{Result, anyhow};
 use uuid::Uuid;
 
-use crate::models::job::{Job, JobStatus};
+use crate::models::job::{CopyStrategy, Job, JobStatus, Owner, Priority, Progress, Resolution, Timestamps};
+use crate::models::query::{JobPage, JobQuery};
+use crate::services::events::{Events, StatusChange};
+use crate::services::journal::{Journal, JournalEvent};
 
 pub struct StorageService {
     jobs: Arc<RwLock<Vec<Arc<Job>>>>,
-} 
+    journal: Journal,
+    events: Events,
+    next_seq: u64, // submission number of the next job added
+}
 
 impl StorageService {
-    pub fn new() -> Self {
-        StorageService { 
-            jobs: Arc::new(RwLock::new(Vec::new()))
+    pub fn new(journal_path: &Path) -> Result<Self> {
+        let (journal, jobs) = Journal::load(journal_path)?;
+
+        // jobs suspended when the daemon stopped have no worker until they are resumed, finished ones until retried
+        for job in &jobs {
+            let mut status = job.status.write().unwrap();
+            // no worker survived the restart, jobs it was working on wait in the queue like the others
+            if matches!(*status, JobStatus::Running | JobStatus::Resumed | JobStatus::Verifying) {
+                *status = JobStatus::Queued;
+            }
+            if *status == JobStatus::Suspended || status.is_finished() {
+                *job.parked.write().unwrap() = true;
+            }
         }
+
+        let next_seq = jobs.iter().map(|job| job.seq + 1).max().unwrap_or(0);
+        Ok(StorageService {
+            jobs: Arc::new(RwLock::new(jobs)),
+            journal,
+            events: Events::default(),
+            next_seq,
+        })
     }
 
     pub fn suspend_job(&mut self, job_id: String) -> bool {
-        Uuid::from_str(&job_id)
-            .ok()
-            .and_then(|uuid| self.jobs.write().unwrap().iter_mut().find(|job| job.id == uuid).cloned())
-            .map(|job| {
-                let mut status = job.status.write().unwrap();
-                *status = JobStatus::Suspended;
-            })
-            .is_some()
+        self.set_job_status(job_id, JobStatus::is_interruptible, JobStatus::Suspended).0
     }
 
-    pub fn resume_job(&mut self, job_id: String) -> bool {
-        Uuid::from_str(&job_id)
-            .ok()
-            .and_then(|uuid| self.jobs.write().unwrap().iter_mut().find(|job| job.id == uuid).cloned())
-            .map(|job| {
-                let mut status = job.status.write().unwrap();
-                *status = JobStatus::Resumed;
-            })
-            .is_some()
+    // Returns whether the job was resumed and, if its worker already let go of it, the job to dispatch again.
+    pub fn resume_job(&mut self, job_id: String) -> (bool, Option<Arc<Job>>) {
+        self.set_job_status(job_id, |status| *status == JobStatus::Suspended, JobStatus::Resumed)
     }
 
-    pub fn cancel_job(&mut self, job_id: String) -> bool {
-        Uuid::from_str(&job_id)
+    // Same as resuming, a parked job needs a worker to clean up after it.
+    pub fn cancel_job(&mut self, job_id: String) -> (bool, Option<Arc<Job>>) {
+        self.set_job_status(job_id, |status| status.is_interruptible() || *status == JobStatus::Suspended, JobStatus::Canceled)
+    }
+
+    // Queues a failed or canceled job again, under the same id, to continue from its last checkpoint with a fresh
+    // set of attempts. A job that failed verification starts over, none of its copy can be trusted.
+    // Same as resuming, returns the job to dispatch if no worker holds it anymore.
+    pub fn requeue_job(&mut self, job_id: String) -> (bool, Option<Arc<Job>>) {
+        let job = match self.job(job_id) {
+            Some(job) => job,
+            None => return (false, None),
+        };
+        // only requeuing changes a finished job's status, and that takes the write lock
+        let restart = *job.status.read().unwrap() == JobStatus::VerificationFailed;
+        let unparked = match self.transition_job(job.clone(), |status| matches!(status, JobStatus::Failed(_) | JobStatus::Canceled | JobStatus::VerificationFailed), JobStatus::Queued) {
+            Some(unparked) => unparked,
+            None => return (false, None),
+        };
+
+        if restart {
+            *job.checkpointed.write().unwrap() = Progress::default();
+            self.record(JournalEvent::checkpoint(&job));
+        }
+        *job.attempts.write().unwrap() = 1;
+        self.record(JournalEvent::attempt(&job));
+        if unparked {
+            StorageService::restore_checkpoint(job.clone());
+        }
+        (true, unparked.then_some(job))
+    }
+
+    fn set_job_status(&self, job_id: String, allowed: fn(&JobStatus) -> bool, new_status: JobStatus) -> (bool, Option<Arc<Job>>) {
+        let job = match Uuid::from_str(&job_id)
             .ok()
-            .and_then(|uuid| self.jobs.write().unwrap().iter_mut().find(|job| job.id == uuid).cloned())
-            .map(|job| {
-                let mut status = job.status.write().unwrap();
-                *status = JobStatus::Canceled;
-            })
-            .is_some()
+            .and_then(|uuid| self.jobs.read().unwrap().iter().find(|job| job.id == uuid).cloned())
+        {
+            Some(job) => job,
+            None => return (false, None),
+        };
+
+        match self.transition_job(job.clone(), allowed, new_status) {
+            Some(unparked) => (true, unparked.then_some(job)),
+            None => (false, None),
+        }
+    }
+
+    // Changes the job's status if its current status is `allowed`. Returns None if it is not, otherwise whether
+    // the job was parked. The status and the parked flag change under the status lock, the same lock held
+    // while a job is parked, so a job that is resumed or canceled is never left without a worker.
+    pub fn transition_job(&self, job: Arc<Job>, allowed: fn(&JobStatus) -> bool, new_status: JobStatus) -> Option<bool> {
+        let mut status = job.status.write().unwrap();
+        if !allowed(&status) {
+            return None;
+        }
+        *status = new_status;
+        self.entered(&job, &status);
+        // still under the status lock, so that concurrent transitions are journaled in the order they happened
+        self.record(JournalEvent::status(&job, &status));
+
+        Some(std::mem::take(&mut *job.parked.write().unwrap()))
+    }
+
+    // Keeps the job's timestamps and throughput in step with its status and lets subscribers know.
+    // Called with the status lock held.
+    fn entered(&self, job: &Arc<Job>, status: &JobStatus) {
+        let bytes = *job.bytes.read().unwrap();
+        match status.is_copying() {
+            true => job.throughput.lock().unwrap().start(bytes),
+            false => job.throughput.lock().unwrap().stop(bytes),
+        }
+
+        let mut timestamps = job.timestamps.write().unwrap();
+        if *status == JobStatus::Running {
+            timestamps.started.get_or_insert_with(Timestamps::now);
+        }
+        // a retried job is no longer finished
+        timestamps.finished = status.is_finished().then(Timestamps::now);
+        drop(timestamps);
+
+        self.events.publish(job, status);
+    }
+
+    // Marks a suspended job as having no worker, resuming it dispatches it again. Returns false if the job is not suspended.
+    pub fn park_job(job: Arc<Job>) -> bool {
+        let status = job.status.read().unwrap();
+        if *status != JobStatus::Suspended {
+            return false;
+        }
+
+        *job.parked.write().unwrap() = true;
+        true
+    }
+
+    // Lets go of a job its worker is done with. A finished job is parked, retrying it dispatches it again.
+    // Returns true if the job was retried while its worker was still wrapping up, it then needs another worker.
+    pub fn release_job(job: Arc<Job>) -> bool {
+        let status = job.status.read().unwrap();
+        if status.is_finished() {
+            *job.parked.write().unwrap() = true;
+        }
+        *status == JobStatus::Queued
     }
 
     pub fn job(&self, job_id: String) -> Option<Arc<Job>> {
@@ -56,24 +163,204 @@ impl StorageService {
             .and_then(|(uuid, jobs)| jobs.iter().find(|job| job.id == uuid).cloned())
     }
 
+    // Every status change from now on, published while the job's status lock is held so that they arrive in order.
+    pub fn subscribe(&self) -> Receiver<StatusChange> {
+        self.events.subscribe()
+    }
+
+    // The page of the jobs the caller can access that match the query, under the read lock of the job list.
+    pub fn query(&self, query: &JobQuery, caller: Option<Owner>) -> JobPage {
+        query.page(self.jobs.read().unwrap().iter().filter(|job| job.is_accessible_by(caller)))
+    }
+
     pub fn jobs(&self) -> Arc<RwLock<Vec<Arc<Job>>>> {
         self.jobs.clone()
     }
 
-    pub fn add_job(&mut self, job: Job) -> Arc<Job> {  
+    // Jobs that were queued or in progress when the daemon last stopped.
+    pub fn unfinished_jobs(&self) -> Vec<Arc<Job>> {
+        self.jobs
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|job| !job.status.read().unwrap().is_finished() && !*job.parked.read().unwrap())
+            .cloned()
+            .collect()
+    }
+
+    pub fn add_job(&mut self, job: Job) -> Arc<Job> {
         let mut jobs = self.jobs.write().unwrap();
-        let job_arc = Arc::new(job);
+        let job_arc = Arc::new(Job { seq: self.next_seq, ..job });
+        self.next_seq += 1;
         jobs.push(Arc::clone(&job_arc));
+        self.record(JournalEvent::created(&job_arc));
+        self.events.publish(&job_arc, &job_arc.status.read().unwrap());
         job_arc
     }
 
-    pub fn increment_job_writes(job: Arc<Job>) {
-        let mut writes = job.writes.write().unwrap(); 
-        *writes += 1;
+    // Forgets the finished jobs `remove` picks, for good: they are dropped from the journal too.
+    // Returns the ids of the removed jobs, or an error if they are gone but the journal could not be compacted.
+    pub fn remove_jobs(&mut self, remove: impl Fn(&Arc<Job>) -> bool) -> Result<Vec<Uuid>> {
+        let mut removed = Vec::new();
+        self.jobs.write().unwrap().retain(|job| {
+            let keep = !job.status.read().unwrap().is_finished() || !remove(job);
+            if !keep {
+                removed.push(job.id);
+            }
+            keep
+        });
+
+        for id in &removed {
+            self.record(JournalEvent::Removed { id: *id });
+        }
+
+        // the events of removed jobs would otherwise stay in the journal until the next restart. Status changes
+        // go through `&self`, none can happen while `&mut self` rewrites the journal
+        if !removed.is_empty() {
+            self.journal
+                .rewrite(&self.jobs.read().unwrap())
+                .map_err(|err| anyhow!("Removed {} job(s), but could not compact the journal: {}", removed.len(), err))?;
+        }
+        Ok(removed)
+    }
+
+    pub fn increment_job_writes(job: Arc<Job>, bytes: u64) {
+        *job.writes.write().unwrap() += 1;
+        *job.stored.write().unwrap() += bytes;
+        StorageService::increment_job_bytes(job, bytes);
+    }
+
+    // Accounts for bytes of the source that did not have to be written, such as holes.
+    pub fn increment_job_bytes(job: Arc<Job>, bytes: u64) {
+        let copied = {
+            let mut copied = job.bytes.write().unwrap();
+            *copied += bytes;
+            *copied
+        };
+        job.throughput.lock().unwrap().record(copied);
+    }
+
+    pub fn increment_job_files(job: Arc<Job>) {
+        *job.files.write().unwrap() += 1;
+        *job.offset.write().unwrap() = 0;
+    }
+
+    // Accounts for a file of a tree that did not need to be copied.
+    pub fn skip_job_file(job: Arc<Job>, bytes: u64) {
+        StorageService::increment_job_bytes(job.clone(), bytes);
+        StorageService::increment_job_files(job);
+    }
+
+    pub fn update_job_method(job: Arc<Job>, method: CopyStrategy) {
+        *job.method.write().unwrap() = Some(method);
     }
 
-    pub fn update_job_status(job: Arc<Job>, new_status: JobStatus) {
+    pub fn update_job_offset(job: Arc<Job>, offset: u64) {
+        *job.offset.write().unwrap() = offset;
+    }
+
+    // Forgets the progress made on the file currently being copied.
+    pub fn rewind_job_file(job: Arc<Job>) {
+        let offset = std::mem::take(&mut *job.offset.write().unwrap());
+        let mut bytes = job.bytes.write().unwrap();
+        *bytes = bytes.saturating_sub(offset);
+    }
+
+    pub fn update_job_status(&self, job: Arc<Job>, new_status: JobStatus) {
         let mut status = job.status.write().unwrap();
         *status = new_status;
+        self.entered(&job, &status);
+        self.record(JournalEvent::status(&job, &status));
+    }
+
+    // Persists the job's progress. Callers must sync the destination and update the job's offset first,
+    // so that everything the checkpoint accounts for is durable at the destination.
+    pub fn checkpoint_job(&self, job: Arc<Job>) {
+        *job.checkpointed.write().unwrap() = Progress::of(&job);
+        self.record(JournalEvent::checkpoint(&job));
+    }
+
+    // Forgets the progress made since the last checkpoint, the next attempt resumes from there.
+    pub fn restore_checkpoint(job: Arc<Job>) {
+        let checkpointed = *job.checkpointed.read().unwrap();
+        *job.writes.write().unwrap() = checkpointed.writes;
+        *job.files.write().unwrap() = checkpointed.files;
+        *job.bytes.write().unwrap() = checkpointed.bytes;
+        *job.stored.write().unwrap() = checkpointed.stored;
+        *job.offset.write().unwrap() = checkpointed.offset;
+    }
+
+    // Marks the job as waiting for its next attempt and counts that attempt. Returns false if the job was suspended
+    // or canceled meanwhile and should not be retried for now, nothing is counted then.
+    pub fn retry_job(&self, job: Arc<Job>, reason: String) -> bool {
+        if self.transition_job(job.clone(), |status| !status.is_finished() && *status != JobStatus::Suspended, JobStatus::Retrying(reason)).is_none() {
+            return false;
+        }
+
+        *job.attempts.write().unwrap() += 1;
+        self.record(JournalEvent::attempt(&job));
+        true
+    }
+
+    pub fn update_job_total(&self, job: Arc<Job>, total: u64) {
+        *job.total.write().unwrap() = Some(total);
+        if let Some(event) = JournalEvent::total(&job) {
+            self.record(event);
+        }
+    }
+
+    pub fn update_job_priority(&self, job: Arc<Job>, priority: Priority) {
+        *job.priority.write().unwrap() = priority;
+        self.record(JournalEvent::priority(&job));
+    }
+
+    pub fn update_job_digest(&self, job: Arc<Job>, digest: String) {
+        *job.digest.write().unwrap() = Some(digest);
+        if let Some(event) = JournalEvent::digest(&job) {
+            self.record(event);
+        }
+    }
+
+    pub fn update_job_resolution(&self, job: Arc<Job>, resolution: Resolution) {
+        *job.resolution.write().unwrap() = Some(resolution);
+        if let Some(event) = JournalEvent::resolved(&job) {
+            self.record(event);
+        }
+    }
+
+    fn record(&self, event: JournalEvent) {
+        if let Err(err) = self.journal.record(&event) {
+            eprintln!("Error writing to journal: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::models::job::JobOptions;
+
+    #[test]
+    fn jobs_verifying_when_the_daemon_stopped_are_queued_again() {
+        let path = std::env::temp_dir().join(format!("copy-service-storage-{}.jsonl", Uuid::new_v4()));
+        let job = Arc::new(Job::new("/a".to_string(), "/a.copy".to_string(), JobOptions::default()));
+        let lines: Vec<String> = [
+            JournalEvent::created(&job),
+            JournalEvent::Status { id: job.id, status: JobStatus::Running, at: Some(10) },
+            JournalEvent::Status { id: job.id, status: JobStatus::Verifying, at: None },
+        ].iter().map(|event| format!("{}\n", serde_json::to_string(event).unwrap())).collect();
+        fs::write(&path, lines.concat()).unwrap();
+
+        let mut storage = StorageService::new(&path).unwrap();
+        let restored = storage.unfinished_jobs();
+
+        assert_eq!(restored.len(), 1);
+        assert_eq!(*restored[0].status.read().unwrap(), JobStatus::Queued);
+        assert!(!*restored[0].parked.read().unwrap());
+        assert!(storage.suspend_job(job.id.to_string()));
+        assert_eq!(*restored[0].status.read().unwrap(), JobStatus::Suspended);
+
+        fs::remove_file(&path).unwrap();
     }
-}
\ No newline at end of file
+}
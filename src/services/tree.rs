@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EntryKind {
+    Directory,
+    File,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub path: PathBuf, // relative to the root of the walk
+    pub kind: EntryKind,
+}
+
+// Walks the tree rooted at `root` depth-first, directories before their contents.
+// Entries are sorted by name so that every walk of an unchanged tree yields the same order.
+// Symlinks and special files are skipped, only directories and regular files are reported.
+pub fn walk(root: &Path) -> Result<Vec<TreeEntry>> {
+    let mut entries = Vec::new();
+    walk_into(root, Path::new(""), &mut entries)?;
+    Ok(entries)
+}
+
+fn walk_into(root: &Path, relative: &Path, entries: &mut Vec<TreeEntry>) -> Result<()> {
+    let mut children: Vec<_> = fs::read_dir(root.join(relative))?.collect::<Result<_, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let path = relative.join(child.file_name());
+        let file_type = child.file_type()?;
+
+        if file_type.is_dir() {
+            entries.push(TreeEntry { path: path.clone(), kind: EntryKind::Directory });
+            walk_into(root, &path, entries)?;
+        } else if file_type.is_file() {
+            entries.push(TreeEntry { path, kind: EntryKind::File });
+        }
+    }
+
+    Ok(())
+}
+
+// Total size in bytes of a regular file, or of every regular file below a directory.
+pub fn size(path: &Path) -> Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    walk(path)?
+        .iter()
+        .filter(|entry| entry.kind == EntryKind::File)
+        .map(|entry| Ok(fs::metadata(path.join(&entry.path))?.len()))
+        .sum()
+}
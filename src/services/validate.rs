@@ -1,5 +1,6 @@
 use std::{fs, sync::Arc, path::Path};
-use crate::models::job::Job;
+use crate::models::job::{ConflictPolicy, Job, Resolution};
+use crate::services::access;
 
 type ValidationResult = (bool, String);
 
@@ -15,6 +16,21 @@ fn are_paths_pointing_to_same_entity(source_path: &str, destination_path: &str)
     }
 }
 
+fn is_destination_inside_source(source_path: &str, destination_path: &str) -> bool {
+    // the destination may not exist yet, so canonicalize its closest existing ancestor instead
+
+    let source_canonical = match fs::canonicalize(source_path) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    Path::new(destination_path)
+        .ancestors()
+        .find_map(|ancestor| fs::canonicalize(ancestor).ok())
+        .map(|ancestor| ancestor.starts_with(&source_canonical))
+        .unwrap_or(false)
+}
+
 pub fn validate(job: Arc<Job>) -> ValidationResult {
     if !Path::new(&job.source).exists() {
         return (false, String::from("Source path does not exist"));
@@ -22,6 +38,66 @@ pub fn validate(job: Arc<Job>) -> ValidationResult {
     if are_paths_pointing_to_same_entity(&job.source, &job.destination) {
         return (false, String::from("Source and destination point to the same entity"))
     } 
+    if Path::new(&job.source).is_dir() && is_destination_inside_source(&job.source, &job.destination) {
+        return (false, String::from("Destination is inside the source directory"))
+    }
+    // the daemon may be able to read and write more than whoever asked for the copy
+    if let Some(owner) = job.owner {
+        if let Err(message) = access::check(owner, &job) {
+            return (false, message);
+        }
+    }
 
     (true, String::from(""))
 }
+
+// Under the update policy a file is copied only if the destination is missing, older than the source
+// or of a different size.
+pub fn is_outdated(source_path: &Path, destination_path: &Path) -> bool {
+    match (fs::metadata(source_path), fs::metadata(destination_path)) {
+        (Ok(source_meta), Ok(destination_meta)) => {
+            let is_newer = matches!(
+                (source_meta.modified(), destination_meta.modified()),
+                (Ok(source_modified), Ok(destination_modified)) if source_modified > destination_modified
+            );
+            is_newer || source_meta.len() != destination_meta.len()
+        }
+        _ => true,
+    }
+}
+
+// First free sibling of `destination_path` of the form "name (n).ext".
+fn available_name(destination_path: &Path) -> String {
+    let stem = destination_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = destination_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| destination_path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .map(|candidate| candidate.to_string_lossy().into_owned())
+        .unwrap()
+}
+
+// Decides what happens to an existing destination according to the job's conflict policy,
+// before any bytes are copied.
+pub fn resolve_conflict(job: Arc<Job>) -> Result<Resolution, String> {
+    let source_path = Path::new(&job.source);
+    let destination_path = Path::new(&job.destination);
+
+    if fs::symlink_metadata(destination_path).is_err() {
+        return Ok(Resolution::Created);
+    }
+
+    match job.options.conflict {
+        ConflictPolicy::Overwrite => Ok(Resolution::Overwritten),
+        ConflictPolicy::Skip => Ok(Resolution::Skipped),
+        ConflictPolicy::Fail => Err(String::from("Destination already exists")),
+        ConflictPolicy::Rename => Ok(Resolution::Renamed(available_name(destination_path))),
+        // the files of a tree are compared one by one while it is copied
+        ConflictPolicy::Update if source_path.is_dir() || is_outdated(source_path, destination_path) => Ok(Resolution::Updated),
+        ConflictPolicy::Update => Ok(Resolution::UpToDate),
+    }
+}